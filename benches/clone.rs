@@ -0,0 +1,55 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use simple_expressions::evaluator::{Evaluator, VariableResolver};
+use simple_expressions::parser;
+use simple_expressions::types::value::Value;
+use simple_expressions::types::{dict, list};
+use std::collections::BTreeMap;
+use std::hint::black_box;
+
+struct BigDictResolver {
+    big_dict: Value,
+}
+
+impl VariableResolver for BigDictResolver {
+    fn resolve(&self, name: &str) -> Option<Value> {
+        if name == "bigDict" { Some(self.big_dict.clone()) } else { None }
+    }
+}
+
+fn big_map() -> BTreeMap<String, Value> {
+    (0..10_000i64).map(|i| (format!("key{}", i), Value::from(i))).collect()
+}
+
+fn dict_clone(c: &mut Criterion) {
+    let map = big_map();
+    let value = dict::new(map.clone());
+
+    // what a clone would cost if dict values owned their map
+    c.bench_function("deep copy of 10k-entry map", |b| b.iter(|| black_box(&map).clone()));
+    c.bench_function("clone of 10k-entry dict value", |b| b.iter(|| black_box(&value).clone()));
+
+    let resolver = BigDictResolver { big_dict: value };
+    let evaluator = Evaluator::new(&resolver);
+    let index = parser::parse_expression("bigDict['key5000']").unwrap();
+    c.bench_function("bigDict['key5000']", |b| b.iter(|| evaluator.evaluate(black_box(&index)).unwrap()));
+}
+
+// Rows of wide dicts, as a query result might be: each step of `rows[500]['key50']` hands out the stored value,
+// so the cost does not depend on the size of what is being indexed.
+fn nested_access(c: &mut Criterion) {
+    let row: BTreeMap<String, Value> = (0..100i64).map(|i| (format!("key{}", i), Value::from(i))).collect();
+    let rows = list::new((0..1_000).map(|_| dict::new(row.clone())).collect());
+    let resolver = BigDictResolver {
+        big_dict: dict::new([("rows".to_string(), rows)].into()),
+    };
+    let evaluator = Evaluator::new(&resolver);
+    let row = parser::parse_expression("bigDict['rows'][500]").unwrap();
+    let cell = parser::parse_expression("bigDict['rows'][500]['key50']").unwrap();
+    let sum = parser::parse_expression("bigDict['rows'].map(@['key50']).sum()").unwrap();
+    c.bench_function("bigDict['rows'][500]", |b| b.iter(|| evaluator.evaluate(black_box(&row)).unwrap()));
+    c.bench_function("bigDict['rows'][500]['key50']", |b| b.iter(|| evaluator.evaluate(black_box(&cell)).unwrap()));
+    c.bench_function("sum of one column over 1k rows", |b| b.iter(|| evaluator.evaluate(black_box(&sum)).unwrap()));
+}
+
+criterion_group!(benches, dict_clone, nested_access);
+criterion_main!(benches);
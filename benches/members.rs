@@ -0,0 +1,33 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use simple_expressions::evaluator::{Evaluator, VariableResolver};
+use simple_expressions::parser;
+use simple_expressions::types::list;
+use simple_expressions::types::value::Value;
+use std::hint::black_box;
+
+struct BigListResolver {
+    big_list: Value,
+}
+
+impl VariableResolver for BigListResolver {
+    fn resolve(&self, name: &str) -> Option<Value> {
+        if name == "bigList" { Some(self.big_list.clone()) } else { None }
+    }
+}
+
+fn member_access(c: &mut Criterion) {
+    let resolver = BigListResolver {
+        big_list: list::new((0..100_000).map(Value::from).collect()),
+    };
+    let evaluator = Evaluator::new(&resolver);
+    let length = parser::parse_expression("bigList.length").unwrap();
+    let get = parser::parse_expression("bigList.get").unwrap();
+    let get_call = parser::parse_expression("bigList.get(50000, 0)").unwrap();
+
+    c.bench_function("bigList.length", |b| b.iter(|| evaluator.evaluate(black_box(&length)).unwrap()));
+    c.bench_function("bigList.get", |b| b.iter(|| evaluator.evaluate(black_box(&get)).unwrap()));
+    c.bench_function("bigList.get(50000, 0)", |b| b.iter(|| evaluator.evaluate(black_box(&get_call)).unwrap()));
+}
+
+criterion_group!(benches, member_access);
+criterion_main!(benches);
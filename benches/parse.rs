@@ -0,0 +1,12 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use simple_expressions::parser;
+use std::hint::black_box;
+
+const EXPRESSION: &str = r#"user.age >= 18 && (user.roles.contains("admin") || limits["daily"] * 2 + 1 > usage.total / 3) && name.trim().toUpper() != "ROOT""#;
+
+fn parse_expression(c: &mut Criterion) {
+    c.bench_function("parse realistic expression", |b| b.iter(|| parser::parse_expression(black_box(EXPRESSION)).unwrap()));
+}
+
+criterion_group!(benches, parse_expression);
+criterion_main!(benches);
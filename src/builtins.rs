@@ -0,0 +1,233 @@
+use crate::evaluator::{Evaluator, VariableResolver};
+use crate::types::error::{Error, Result};
+use crate::types::function::{Callable, callable0, callable1};
+use crate::types::primitive::Primitive;
+use crate::types::value::Value;
+use crate::types::{dict, list, range};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Global functions available to every evaluator. They are looked up after the registry and the resolver, so
+// neither registered functions nor resolver variables are ever shadowed by a builtin.
+pub(crate) fn defaults(rng: &Rc<Rng>) -> HashMap<String, Callable> {
+    let mut builtins = HashMap::new();
+    builtins.insert("type".into(), callable1(|arg: &Value| Ok(Value::from(arg.type_name()))));
+    // shorthands for `type(x) == "..."`
+    for (name, type_name) in [("isNumber", "number"), ("isString", "string"), ("isBool", "bool"), ("isList", "list"), ("isDict", "dict"), ("isNull", "null")] {
+        builtins.insert(name.into(), callable1(move |arg: &Value| Ok(Value::from(arg.type_name() == type_name))));
+    }
+    builtins.insert("len".into(), callable1(len));
+    install_random(&mut builtins, rng);
+    #[cfg(feature = "uuid")]
+    install_uuid(&mut builtins, rng);
+    #[cfg(feature = "chrono")]
+    install_datetime(&mut builtins);
+    builtins
+}
+
+// Characters of a string, elements of a list, entries of a dict: `.length` of any of them, as a function.
+fn len(arg: &Value) -> Result<Value> {
+    let n = if let Value::Primitive(Primitive::Str(s)) = arg {
+        s.chars().count()
+    } else if let Some(items) = list::as_list(arg) {
+        items.items().len()
+    } else if let Some(d) = arg.downcast_ref::<dict::DictObject>() {
+        d.entries().len()
+    } else if let Some(r) = range::as_range(arg) {
+        r.len()
+    } else {
+        return Err(Error::TypeMismatch(format!("len expects a string, list, range or dict, got {}", arg.type_name())));
+    };
+    Ok(Value::from(n as i64))
+}
+
+// SplitMix64: small, fast and good enough for sampling; not suitable for anything security related.
+pub(crate) struct Rng {
+    state: Cell<u64>,
+}
+
+impl Rng {
+    pub(crate) fn from_entropy() -> Rng {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default();
+        Rng::seeded(RandomState::new().hash_one(nanos))
+    }
+
+    pub(crate) fn seeded(seed: u64) -> Rng {
+        Rng { state: Cell::new(seed) }
+    }
+
+    pub(crate) fn reseed(&self, seed: u64) {
+        self.state.set(seed);
+    }
+
+    fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9E3779B97F4A7C15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniform in [lo, hi], both inclusive
+    fn next_in_range(&self, lo: i64, hi: i64) -> i64 {
+        let span = (hi as i128 - lo as i128 + 1) as u128;
+        let offset = (self.next_u64() as u128 * span) >> 64;
+        (lo as i128 + offset as i128) as i64
+    }
+}
+
+fn install_random(builtins: &mut HashMap<String, Callable>, rng: &Rc<Rng>) {
+    let r = Rc::clone(rng);
+    builtins.insert("random".into(), callable0(move || Ok(Value::from(r.next_f64()))));
+    let r = Rc::clone(rng);
+    builtins.insert(
+        "randomInt".into(),
+        Rc::new(move |args: &[Value]| -> Result<Value> {
+            let [Value::Primitive(Primitive::Int(lo)), Value::Primitive(Primitive::Int(hi))] = args else {
+                return Err(Error::TypeMismatch("randomInt expects 2 int args".into()));
+            };
+            if lo > hi {
+                return Err(Error::EvaluationFailed(format!("randomInt: empty range {}..{}", lo, hi)));
+            }
+            Ok(Value::from(r.next_in_range(*lo, *hi)))
+        }),
+    );
+}
+
+/// Registers the math functions `abs`, `floor`, `ceil`, `round`, `sqrt`, `pow`, `min` and `max` with `ev`. They are
+/// not available by default, and like any registered function they shadow resolver variables of the same name.
+pub fn install_math<R: VariableResolver>(ev: &mut Evaluator<R>) {
+    for (name, f) in math() {
+        ev.register(name, move |args: &[Value]| f(args));
+    }
+}
+
+fn math() -> Vec<(&'static str, Callable)> {
+    vec![
+        (
+            "abs",
+            callable1(|arg: &Value| match number("abs", arg)? {
+                Number::Int(i) => i.checked_abs().map(Value::from).ok_or_else(|| Error::IntegerOverflow(format!("abs of {}", i))),
+                Number::Float(f) => Ok(Value::from(f.abs())),
+            }),
+        ),
+        ("floor", rounding("floor", f64::floor)),
+        ("ceil", rounding("ceil", f64::ceil)),
+        ("round", rounding("round", f64::round)),
+        (
+            "sqrt",
+            callable1(|arg: &Value| {
+                let f = number("sqrt", arg)?.to_f64();
+                if f < 0.0 {
+                    return Err(Error::EvaluationFailed(format!("sqrt of a negative number: {}", f)));
+                }
+                Ok(Value::from(f.sqrt()))
+            }),
+        ),
+        (
+            "pow",
+            Rc::new(|args: &[Value]| -> Result<Value> {
+                // always a float, like `^`
+                let [base, exp] = args else {
+                    return Err(Error::EvaluationFailed("pow expects 2 args".into()));
+                };
+                Ok(Value::from(number("pow", base)?.to_f64().powf(number("pow", exp)?.to_f64())))
+            }),
+        ),
+        ("min", extreme("min", Ordering::Less)),
+        ("max", extreme("max", Ordering::Greater)),
+    ]
+}
+
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn to_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+fn number(function: &str, v: &Value) -> Result<Number> {
+    match v {
+        Value::Primitive(Primitive::Int(i)) => Ok(Number::Int(*i)),
+        Value::Primitive(Primitive::Float(f)) => Ok(Number::Float(*f)),
+        _ => Err(Error::TypeMismatch(format!("{} expects numbers, got {}", function, v.type_name()))),
+    }
+}
+
+// floor, ceil and round: ints pass through, and a float result that fits in an int becomes one
+fn rounding(function: &'static str, op: fn(f64) -> f64) -> Callable {
+    callable1(move |arg: &Value| match number(function, arg)? {
+        Number::Int(i) => Ok(Value::from(i)),
+        Number::Float(f) => {
+            let r = op(f);
+            if r.is_finite() && r >= i64::MIN as f64 && r < i64::MAX as f64 {
+                Ok(Value::from(r as i64))
+            } else {
+                Ok(Value::from(r))
+            }
+        }
+    })
+}
+
+// min and max of one or more numbers; the first of several equal arguments is returned unchanged
+fn extreme(function: &'static str, wanted: Ordering) -> Callable {
+    Rc::new(move |args: &[Value]| -> Result<Value> {
+        let Some(first) = args.first() else {
+            return Err(Error::EvaluationFailed(format!("{} expects at least 1 arg", function)));
+        };
+        let mut best = (first, number(function, first)?.to_f64());
+        for v in &args[1..] {
+            let f = number(function, v)?.to_f64();
+            if f.partial_cmp(&best.1) == Some(wanted) {
+                best = (v, f);
+            }
+        }
+        Ok(best.0.clone())
+    })
+}
+
+#[cfg(feature = "uuid")]
+fn install_uuid(builtins: &mut HashMap<String, Callable>, rng: &Rc<Rng>) {
+    let r = Rc::clone(rng);
+    builtins.insert(
+        "uuid".into(),
+        callable0(move || {
+            let mut bytes = [0u8; 16];
+            bytes[..8].copy_from_slice(&r.next_u64().to_le_bytes());
+            bytes[8..].copy_from_slice(&r.next_u64().to_le_bytes());
+            Ok(Value::from(uuid::Builder::from_random_bytes(bytes).into_uuid().hyphenated().to_string()))
+        }),
+    );
+}
+
+#[cfg(feature = "chrono")]
+fn install_datetime(builtins: &mut HashMap<String, Callable>) {
+    use crate::types::datetime;
+
+    builtins.insert("now".into(), callable0(|| Ok(datetime::new(chrono::Utc::now()))));
+    builtins.insert(
+        "datetime".into(),
+        callable1(|arg: &Value| match arg {
+            Value::Primitive(Primitive::Str(s)) => datetime::parse(s),
+            _ => Err(Error::TypeMismatch("datetime expects an ISO-8601 string".into())),
+        }),
+    );
+}
@@ -1,61 +1,299 @@
+use crate::builtins::{self, Rng};
 use crate::parser;
+use crate::resolvers::NoVars;
+#[cfg(feature = "decimal")]
+use crate::types::decimal;
 use crate::types::error::{Error, Result};
-use crate::types::expression::{BinaryOp, Expr, UnaryOp};
+use crate::types::expression::{BinaryOp, Expr, UnaryOp, binary_symbol};
+use crate::types::function::{self, Callable};
 use crate::types::primitive::Primitive;
+use crate::types::string_members::{get_string_index, natural_cmp};
 use crate::types::value::Value;
-use crate::types::{dict, list};
+use crate::types::visitor::{Visitor, VisitorMut, walk_expr, walk_expr_mut};
+use crate::types::{dict, list, range, set};
+use crate::validator;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::rc::{Rc, Weak};
 
 pub fn evaluate<T: VariableResolver>(input: &str, resolver: &T) -> Result<Value> {
-    let expr = parser::parse_expression(input)?;
-    let evaluator = Evaluator::new(resolver);
-    let result = evaluator.evaluate(&expr).map_err(|e| Error::EvaluationFailed(format!("evaluation error: {}", e)))?;
-    Ok(result)
+    CompiledExpr::compile(input)?.eval(resolver)
 }
 
-pub fn evaluate_interpolations<T: VariableResolver>(input: &str, resolver: &T) -> Result<String> {
-    let mut out = String::new();
-    let mut rest = input;
-    while let Some(idx) = rest.find("${") {
-        // copy literal part before the interpolation
-        out.push_str(&rest[..idx]);
-        let after = &rest[idx + 2..];
-        let (expr, consumed) = parser::parse_internal(after, parser::Rule::delimited_expr)?;
+/// An expression parsed once so it can be evaluated many times, e.g. once per row, without re-parsing.
+/// `eval` behaves exactly like `evaluate` on the original source. Compiled expressions are equal, and hash alike,
+/// when their expressions are, however differently the sources were laid out.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    expr: Expr,
+    // the source position of every node of `expr`, see `parser::parse_spanned`
+    spans: Vec<Range<usize>>,
+}
+
+impl PartialEq for CompiledExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
+    }
+}
+
+impl Eq for CompiledExpr {}
+
+impl Hash for CompiledExpr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.expr.hash(state);
+    }
+}
+
+impl CompiledExpr {
+    pub fn compile(src: &str) -> Result<CompiledExpr> {
+        let (expr, spans) = parser::parse_spanned(src)?;
+        Ok(CompiledExpr { expr, spans })
+    }
+
+    /// Evaluation errors carry the span of the sub-expression that failed (`Error::span`), such as the variable
+    /// that could not be resolved.
+    pub fn eval<T: VariableResolver>(&self, resolver: &T) -> Result<Value> {
         let evaluator = Evaluator::new(resolver);
-        let result = evaluator.evaluate(&expr).map_err(|e| Error::EvaluationFailed(format!("evaluation error: {}", e)))?;
-        let result_str = result.to_string();
-        out.push_str(result_str.as_str());
-        rest = &after[consumed..];
+        evaluator.evaluate_spanned(&self.expr, &self.spans, 0)
+    }
+
+    /// Folds the constant sub-expressions, see `Expr::fold_constants`, so that each `eval` does less work. Errors
+    /// keep their spans: a folded node takes the position of the sub-expression it replaced.
+    pub fn fold_constants(self) -> CompiledExpr {
+        let folded = self.expr.clone().fold_constants();
+        let mut spans = Vec::with_capacity(self.spans.len());
+        folded_spans(&self.expr, &self.spans, &folded, &mut spans);
+        CompiledExpr { expr: folded, spans }
+    }
+
+    /// `validator::validate` on the expression, with each error located at the sub-expression it is about.
+    pub fn validate(&self) -> Vec<Error> {
+        validator::validate_spanned(&self.expr, Some(&self.spans))
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
     }
-    // copy the remainder
-    out.push_str(rest);
+}
+
+pub fn evaluate_interpolations<T: VariableResolver>(input: &str, resolver: &T) -> Result<String> {
+    let mut out = String::new();
+    evaluate_interpolations_to(input, resolver, &mut out)?;
     Ok(out)
 }
 
+/// Like `evaluate_interpolations`, but writes literal text and evaluated values straight into `out` as the input is
+/// scanned instead of building a `String`. On error, `out` holds the output produced before the failing segment.
+/// Uses a default `Evaluator`; see `Evaluator::evaluate_interpolated_to` for one with functions or options.
+pub fn evaluate_interpolations_to<T: VariableResolver, W: fmt::Write>(input: &str, resolver: &T, out: &mut W) -> Result<()> {
+    Evaluator::new(resolver).evaluate_interpolated_to(input, out)
+}
+
+// Recognises `name:-fallback}` (the text after `${`), where name is a variable or dotted path and fallback is
+// literal text up to the first `}`. Returns the name, the fallback and the number of bytes consumed.
+fn split_default(s: &str) -> Option<(&str, &str, usize)> {
+    let (head, _) = s.split_once(":-")?;
+    let name = head.trim();
+    let mut chars = name.chars();
+    if !chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+        return None;
+    }
+    let fallback_start = head.len() + 2;
+    let fallback_len = s[fallback_start..].find('}')?;
+    Some((name, &s[fallback_start..fallback_start + fallback_len], fallback_start + fallback_len + 1))
+}
+
 pub trait VariableResolver {
     fn resolve(&self, name: &str) -> Option<Value>;
+
+    /// Like `resolve`, but able to report a failed lookup (a backend that is down, say) as an error instead of as
+    /// a missing variable. The evaluator always calls this; the default delegates to `resolve`.
+    fn try_resolve(&self, name: &str) -> Result<Option<Value>> {
+        Ok(self.resolve(name))
+    }
 }
 
 pub struct Evaluator<'a, R: VariableResolver> {
     resolver: &'a R,
+    // shared with the functions created for `@` arguments, so they are behind an Rc
+    functions: Rc<HashMap<String, Callable>>,
+    builtins: Rc<HashMap<String, Callable>>,
+    rng: Rc<Rng>,
+    lenient_vars: bool,
+    ieee_division: bool,
+    max_result_size: Option<usize>,
+    natural_string_order: bool,
+    max_depth: usize,
+    // how deeply `evaluate` is currently nested, shared with the functions created for `@` arguments so their
+    // bodies count towards the same limit
+    depth: Rc<Cell<usize>>,
+    // where evaluation is in the expression given to `evaluate_spanned`, if that is what is being evaluated
+    cursor: Option<Rc<Cursor>>,
+    // inside an interpolation, string literals are interpolated too
+    interpolate_strings: bool,
+    // inside the body of an implicit function `@` is taken, so method arguments that use it are evaluated rather
+    // than becoming functions of their own
+    current_bound: bool,
+    // the names bound by `let` and by the parameters of the function being called, looked up before anything else
+    scope: Option<Rc<Scope>>,
+    // the resolver for the functions created during evaluation, see `Root`; only the evaluator `new` creates
+    // holds it strongly
+    root: Weak<Root>,
+    _root: Option<Rc<Root>>,
 }
 
+/// The default for `Evaluator::max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
 impl<'a, R: VariableResolver> Evaluator<'a, R> {
     pub fn new(resolver: &'a R) -> Self {
-        Self { resolver }
+        let rng = Rc::new(Rng::from_entropy());
+        let root = Rc::new(Root::new(resolver));
+        Self {
+            resolver,
+            functions: Rc::new(HashMap::new()),
+            builtins: Rc::new(builtins::defaults(&rng)),
+            rng,
+            lenient_vars: false,
+            ieee_division: false,
+            max_result_size: None,
+            natural_string_order: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth: Rc::new(Cell::new(0)),
+            cursor: None,
+            interpolate_strings: false,
+            current_bound: false,
+            scope: None,
+            root: Rc::downgrade(&root),
+            _root: Some(root),
+        }
+    }
+
+    /// When enabled, variables the resolver cannot find evaluate to `null` instead of failing with
+    /// `Error::ResolveFailed`. Strict mode (the default) is better at catching typos.
+    pub fn lenient_vars(mut self, lenient: bool) -> Self {
+        self.lenient_vars = lenient;
+        self
+    }
+
+    /// When enabled, float division (or remainder) by zero follows IEEE 754 and produces `inf`, `-inf` or `NaN`
+    /// instead of failing with `Error::DivideByZero`. Division of two integers by zero is always an error.
+    pub fn ieee_division(mut self, ieee: bool) -> Self {
+        self.ieee_division = ieee;
+        self
+    }
+
+    /// Limits the size of every string (in characters), list and dict (in elements) that evaluation produces;
+    /// anything larger fails with `Error::ResultTooLarge`. String concatenation and repetition are checked before
+    /// the result is built, other values (method results, literals) as soon as they are produced. Unlimited by
+    /// default.
+    pub fn max_result_size(mut self, limit: usize) -> Self {
+        self.max_result_size = Some(limit);
+        self
+    }
+
+    /// When enabled, `<`, `<=`, `>` and `>=` compare strings in natural order, where runs of digits compare by
+    /// numeric value: `"file2" < "file10"` and `"1.9" < "1.10"` become true. By default strings compare
+    /// character by character (by code point).
+    pub fn natural_string_order(mut self, natural: bool) -> Self {
+        self.natural_string_order = natural;
+        self
+    }
+
+    /// Limits how deeply sub-expressions may nest during evaluation (parentheses, operands, arguments, and the
+    /// bodies of `@` functions); deeper expressions fail with `Error::RecursionLimit` instead of overflowing the
+    /// stack. Defaults to `DEFAULT_MAX_DEPTH`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Seeds the random number generator behind `random()`, `randomInt()` and `uuid()` so that results are
+    /// reproducible. By default every evaluator is seeded from the OS.
+    pub fn rng_seed(self, seed: u64) -> Self {
+        self.rng.reseed(seed);
+        self
+    }
+
+    /// Registers a named global function. Registered functions take precedence over the resolver, so a
+    /// resolver variable with the same name is shadowed by the function.
+    pub fn register<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        Rc::make_mut(&mut self.functions).insert(name.to_string(), Rc::new(f));
     }
 
     pub fn evaluate(&self, expr: &Expr) -> Result<Value> {
+        let Some(cursor) = &self.cursor else {
+            return self.evaluate_nested(expr);
+        };
+        let node = cursor.enter();
+        let result = self.evaluate_nested(expr);
+        cursor.leave(node, result.is_err());
+        result
+    }
+
+    // `evaluate`, one level deeper
+    fn evaluate_nested(&self, expr: &Expr) -> Result<Value> {
+        let depth = self.depth.get();
+        if depth >= self.max_depth {
+            return Err(Error::RecursionLimit(self.max_depth));
+        }
+        self.depth.set(depth + 1);
+        let result = self.eval_expr(expr);
+        self.depth.set(depth);
+        result
+    }
+
+    // Evaluates a parsed expression whose nodes' source spans are `spans` (from `parser::parse_spanned`), offset
+    // by `offset` bytes. An error is located at the innermost sub-expression that failed.
+    fn evaluate_spanned(&self, expr: &Expr, spans: &[Range<usize>], offset: usize) -> Result<Value> {
+        let cursor = Rc::new(Cursor::new(expr));
+        let mut evaluator = self.with_resolver(self.resolver);
+        evaluator.cursor = Some(Rc::clone(&cursor));
+        evaluator.evaluate(expr).map_err(|e| {
+            let error = Error::EvaluationFailed(format!("evaluation error: {}", e));
+            match cursor.failed.get().and_then(|node| spans.get(node)) {
+                Some(span) => error.at(span.start + offset..span.end + offset),
+                None => error,
+            }
+        })
+    }
+
+    fn eval_expr(&self, expr: &Expr) -> Result<Value> {
         match expr {
+            Expr::Literal(Primitive::Str(s)) if self.interpolate_strings && s.contains("${") => {
+                // the expressions inside have no place in the source of this one, so a failure is located at the literal
+                let mut inner = self.with_resolver(self.resolver);
+                inner.cursor = None;
+                let mut out = String::new();
+                inner.interpolate_to(s, &mut out, true).map(|_| Value::from(out))
+            }
             Expr::Literal(p) => Ok(Value::Primitive(p.clone())),
             Expr::Var(name) => self.eval_var(name),
+            Expr::Lambda { params, body } => Ok(self.closure(params.clone(), body, true)),
+            Expr::Let { name, value, body } => self.eval_let(name, value, body),
+            Expr::Assign { object, field, value } => {
+                let target = self.evaluate(object)?;
+                let value = self.evaluate(value)?;
+                target.set_member(field, value.clone())?;
+                Ok(value)
+            }
             Expr::ListLiteral(items) => {
                 let mut vals = Vec::with_capacity(items.len());
+                self.check_size(items.len())?;
                 for e in items {
                     vals.push(self.evaluate(e)?);
                 }
                 Ok(list::new(vals))
             }
             Expr::DictLiteral(pairs) => {
+                self.check_size(pairs.len())?;
                 let mut map = std::collections::BTreeMap::new();
                 for (k_expr, v_expr) in pairs {
                     // evaluate key first, then value, left-to-right
@@ -71,11 +309,15 @@ impl<'a, R: VariableResolver> Evaluator<'a, R> {
                 }
                 Ok(dict::new(map))
             }
-            Expr::Call { callee, args } => self.eval_call(callee, args),
-            Expr::Member { object, field } => {
-                let obj = self.evaluate(object)?;
-                obj.get_member(field)
+            Expr::SetLiteral(items) => {
+                self.check_size(items.len())?;
+                set::from_values(items.iter().map(|e| self.evaluate(e)).collect::<Result<_>>()?)
             }
+            Expr::Call { callee, args } => self.eval_call(callee, args),
+            Expr::Member { .. } => match self.eval_path(expr) {
+                Err(Error::ResolveFailed(name)) if self.lenient_vars && names_path(expr, &name) => Ok(Value::Primitive(Primitive::Null)),
+                other => other,
+            },
             Expr::Index { object, index } => {
                 let obj_v = self.evaluate(object)?;
                 match obj_v {
@@ -89,15 +331,15 @@ impl<'a, R: VariableResolver> Evaluator<'a, R> {
                             Err(Error::NotIndexable(idx_v.as_str_lossy()))
                         }
                     }
-                    other => {
-                        let t = match other {
-                            Value::Primitive(Primitive::Int(_)) | Value::Primitive(Primitive::Float(_)) => "number",
-                            Value::Primitive(Primitive::Str(_)) => "string",
-                            Value::Primitive(Primitive::Bool(_)) => "bool",
-                            Value::Object(obj) => obj.type_name(),
-                        };
-                        Err(Error::NotIndexable(t.into()))
+                    Value::Primitive(Primitive::Str(s)) => {
+                        let idx_v = self.evaluate(index)?;
+                        if let Value::Primitive(Primitive::Int(i)) = idx_v {
+                            get_string_index(&s, i)
+                        } else {
+                            Err(Error::NotIndexable(idx_v.as_str_lossy()))
+                        }
                     }
+                    other => Err(Error::NotIndexable(other.type_name().into())),
                 }
             }
             Expr::Unary { op, expr } => {
@@ -107,39 +349,346 @@ impl<'a, R: VariableResolver> Evaluator<'a, R> {
                         let b = v.coerce_bool().ok_or(Error::TypeMismatch("'!' expects bool".into()))?;
                         Ok(Value::Primitive(Primitive::Bool(!b)))
                     }
-                    UnaryOp::Neg => {
-                        let v = self.evaluate(expr)?;
-                        match v {
-                            Value::Primitive(Primitive::Int(i)) => Ok(Value::Primitive(Primitive::Int(-i))),
-                            Value::Primitive(Primitive::Float(f)) => Ok(Value::Primitive(Primitive::Float(-f))),
-                            _ => Err(Error::TypeMismatch("'-' expects number".into())),
-                        }
-                    }
+                    UnaryOp::Neg => match v {
+                        Value::Primitive(Primitive::Int(i)) => i.checked_neg().map(Value::from).ok_or_else(|| Error::IntegerOverflow(format!("-({})", i))),
+                        Value::Primitive(Primitive::Float(f)) => Ok(Value::Primitive(Primitive::Float(-f))),
+                        #[cfg(feature = "decimal")]
+                        Value::Primitive(Primitive::Decimal(d)) => Ok(Value::Primitive(Primitive::Decimal(-d))),
+                        _ => Err(Error::TypeMismatch("'-' expects number".into())),
+                    },
                 }
             }
             Expr::Binary { op, left, right } => self.eval_binary(*op, left, right),
+            Expr::Compare { first, rest } => self.eval_chain(first, rest),
+        }
+    }
+
+    /// Statically checks `expr` for errors that are certain to happen at runtime, without evaluating it or
+    /// consulting the resolver. See `validator::validate`.
+    pub fn validate(&self, expr: &Expr) -> Vec<Error> {
+        validator::validate(expr)
+    }
+
+    /// Simplifies `expr` using only the variables the resolver knows, leaving the rest symbolic: with `x = 2`
+    /// known, `x + y` becomes `2 + y` and `x * 3 + y` becomes `6 + y`. Known variables are inlined when their
+    /// value can be written as a literal (primitives, and lists and dicts of them); functions and other objects
+    /// stay as references. Sub-expressions that fail to evaluate are left as they are, so an error is only
+    /// reported if the final evaluation actually reaches them. Lenient mode does not apply here: an unknown
+    /// variable is never replaced by `null`.
+    pub fn partial_evaluate(&self, expr: &Expr) -> Expr {
+        let mut folded = expr.clone();
+        PartialEvaluator { evaluator: self }.visit_expr_mut(&mut folded);
+        folded
+    }
+
+    /// Evaluates `expr` and converts the result into `T`, e.g. `ev.evaluate_typed::<i64>(&expr)?`. A result of the
+    /// wrong type is reported as `Error::TypeMismatch`.
+    pub fn evaluate_typed<T>(&self, expr: &Expr) -> Result<T>
+    where
+        T: TryFrom<Value, Error = Error>,
+    {
+        T::try_from(self.evaluate(expr)?)
+    }
+
+    /// `evaluate_interpolations_to` with this evaluator's registered functions and options.
+    pub fn evaluate_interpolated_to<W: fmt::Write>(&self, input: &str, out: &mut W) -> Result<()> {
+        let mut evaluator = self.with_resolver(self.resolver);
+        evaluator.interpolate_strings = true;
+        evaluator.interpolate_to(input, out, false)
+    }
+
+    // This evaluator looking variables up in `resolver` instead, with the same functions and options and sharing
+    // its depth.
+    fn with_resolver<'b, S: VariableResolver>(&self, resolver: &'b S) -> Evaluator<'b, S> {
+        Evaluator {
+            resolver,
+            functions: Rc::clone(&self.functions),
+            builtins: Rc::clone(&self.builtins),
+            rng: Rc::clone(&self.rng),
+            lenient_vars: self.lenient_vars,
+            ieee_division: self.ieee_division,
+            max_result_size: self.max_result_size,
+            natural_string_order: self.natural_string_order,
+            max_depth: self.max_depth,
+            depth: Rc::clone(&self.depth),
+            cursor: self.cursor.clone(),
+            interpolate_strings: self.interpolate_strings,
+            current_bound: self.current_bound,
+            scope: self.scope.clone(),
+            root: Weak::clone(&self.root),
+            _root: None,
         }
     }
 
     fn eval_var(&self, name: &str) -> Result<Value> {
-        match self.resolver.resolve(name) {
+        match self.resolve_name(name)? {
             Some(v) => Ok(v),
+            None if self.lenient_vars => Ok(Value::Primitive(Primitive::Null)),
             None => Err(Error::ResolveFailed(name.to_string())),
         }
     }
 
+    fn resolve_name(&self, name: &str) -> Result<Option<Value>> {
+        if let Some(v) = self.scope.as_ref().and_then(|scope| scope.get(name)) {
+            return Ok(Some(v));
+        }
+        if let Some(f) = self.functions.get(name) {
+            return Ok(Some(function::new(Rc::clone(f))));
+        }
+        Ok(self.resolver.try_resolve(name)?.or_else(|| self.builtins.get(name).map(|f| function::new(Rc::clone(f)))))
+    }
+
+    // Evaluates a member chain such as `a.b.c`. The leading variable is resolved first and the rest is member
+    // access; only if a prefix cannot be resolved is the flattened name (`a.b`, then `a.b.c`) looked up instead, so
+    // a chain that resolves never builds a name. A chain that does not start at a variable is evaluated as usual.
+    fn eval_path(&self, expr: &Expr) -> Result<Value> {
+        match expr {
+            Expr::Var(name) => self.resolve_name(name)?.ok_or_else(|| Error::ResolveFailed(name.clone())),
+            Expr::Member { object, field } => {
+                let base = match &**object {
+                    Expr::Var(_) | Expr::Member { .. } => {
+                        // the object is part of the path rather than evaluated on its own
+                        if let Some(cursor) = &self.cursor {
+                            cursor.enter();
+                        }
+                        self.eval_path(object)
+                    }
+                    _ => self.evaluate(object),
+                };
+                match base {
+                    Ok(obj) => obj.get_member(field),
+                    Err(Error::ResolveFailed(name)) if names_path(object, &name) => {
+                        let path = format!("{}.{}", name, field);
+                        self.resolve_name(&path)?.ok_or(Error::ResolveFailed(path))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            _ => self.evaluate(expr),
+        }
+    }
+
     fn eval_call(&self, callee: &Expr, args: &Vec<Expr>) -> Result<Value> {
+        if matches!(callee, Expr::Var(name) if name == "try") {
+            return self.eval_try(args);
+        }
         let callee_v = self.evaluate(callee)?;
-        match callee_v {
-            Value::Object(obj) => {
-                let mut vals = Vec::with_capacity(args.len());
-                for a in args {
-                    vals.push(self.evaluate(a)?);
+        if !matches!(callee_v, Value::Object(_)) {
+            return Err(Error::NotCallable);
+        }
+        let method_call = matches!(callee, Expr::Member { .. });
+        let mut vals = Vec::with_capacity(args.len());
+        for a in args {
+            if method_call && !self.current_bound && is_implicit_function(a) {
+                if let Some(cursor) = &self.cursor {
+                    cursor.skip();
+                }
+                vals.push(self.implicit_function(a));
+            } else {
+                vals.push(self.evaluate(a)?);
+            }
+        }
+        let result = callee_v.call(&vals)?;
+        self.check_size(value_size(&result))?;
+        Ok(result)
+    }
+
+    fn check_size(&self, size: usize) -> Result<()> {
+        match self.max_result_size {
+            Some(limit) if size > limit => Err(Error::ResultTooLarge { size, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    // A method argument that mentions `@` is passed as a function of one argument instead of being evaluated, so
+    // `xs.map(@ * 2)` hands `map` a function computing `@ * 2` for each element. Extra arguments are ignored and a
+    // missing one is null. Only the outermost such argument is a function: in its body every `@` is that element,
+    // so `@.substring(0, @.length - 1)` evaluates its arguments as usual.
+    fn implicit_function(&self, body: &Expr) -> Value {
+        self.closure(vec![parser::CURRENT.to_string()], body, false)
+    }
+
+    // The function value of `|x, y| body`. A call binds the parameters to its arguments in front of the scope the
+    // function was created in; other names are looked up as the body needs them, with the resolver of the evaluator
+    // that created the function. `strict` requires exactly one argument per parameter.
+    fn closure(&self, params: Vec<String>, body: &Expr, strict: bool) -> Value {
+        let body = body.clone();
+        // the evaluator for the body, once it has a resolver
+        let mut outer = self.with_resolver(&NoVars);
+        // the body is a copy, so its nodes have no place in the source; a failure is located at the call
+        outer.cursor = None;
+        outer.current_bound = self.current_bound || params.iter().any(|p| p == parser::CURRENT);
+        function::new(Rc::new(move |args: &[Value]| {
+            if strict && args.len() != params.len() {
+                return Err(Error::EvaluationFailed(format!("function of {} args called with {}", params.len(), args.len())));
+            }
+            let root = RootResolver(Weak::clone(&outer.root));
+            let mut evaluator = outer.with_resolver(&root);
+            let values = (0..params.len()).map(|i| args.get(i).cloned().unwrap_or(Value::Primitive(Primitive::Null))).collect();
+            evaluator.scope = Some(Rc::new(Scope {
+                names: params.clone(),
+                values,
+                parent: outer.scope.clone(),
+            }));
+            evaluator.evaluate(&body)
+        }))
+    }
+
+    // `let name = value in body`: the body is evaluated with `name` bound in front of the current scope.
+    fn eval_let(&self, name: &str, value: &Expr, body: &Expr) -> Result<Value> {
+        let value = self.evaluate(value)?;
+        let mut evaluator = self.with_resolver(self.resolver);
+        evaluator.scope = Some(Rc::new(Scope {
+            names: vec![name.to_string()],
+            values: vec![value],
+            parent: self.scope.clone(),
+        }));
+        evaluator.evaluate(body)
+    }
+
+    // `try(expr, default)` is a special form rather than a function: `expr` must not be evaluated before the
+    // call, and `default` is only evaluated when `expr` fails.
+    fn eval_try(&self, args: &[Expr]) -> Result<Value> {
+        let [expr, default] = args else {
+            return Err(Error::EvaluationFailed("try expects 2 args".into()));
+        };
+        // the `try` callee is never evaluated
+        if let Some(cursor) = &self.cursor {
+            cursor.skip();
+        }
+        self.evaluate(expr).or_else(|_| {
+            if let Some(cursor) = &self.cursor {
+                cursor.failed.set(None);
+            }
+            self.evaluate(default)
+        })
+    }
+
+    // Writes `input` with its `${...}` segments evaluated. At the top level errors are located in `input`; a
+    // `nested` call interpolates a string literal and returns errors as they are, to be located at the literal.
+    fn interpolate_to<W: fmt::Write>(&self, input: &str, out: &mut W, nested: bool) -> Result<()> {
+        let write_failed = |_| Error::EvaluationFailed("failed to write interpolation output".into());
+        let mut rest = input;
+        while let Some(idx) = rest.find("${") {
+            // copy literal part before the interpolation. `\${` is a literal `${`, and `\\${` a literal backslash
+            // followed by an interpolation; other backslashes are plain text.
+            let literal = &rest[..idx];
+            match literal.strip_suffix('\\') {
+                Some(escaped) if !escaped.ends_with('\\') => {
+                    out.write_str(escaped).map_err(write_failed)?;
+                    out.write_str("${").map_err(write_failed)?;
+                    rest = &rest[idx + 2..];
+                    continue;
+                }
+                Some(backslash) => out.write_str(backslash).map_err(write_failed)?,
+                None => out.write_str(literal).map_err(write_failed)?,
+            }
+            let after = &rest[idx + 2..];
+            if let Some((name, fallback, consumed)) = split_default(after) {
+                // shell-style `${name:-fallback}`: the literal fallback replaces an unresolved, null or empty variable
+                let expr = parser::parse_expression(name)?;
+                let value = match self.evaluate(&expr) {
+                    Err(Error::ResolveFailed(_)) | Ok(Value::Primitive(Primitive::Null)) => None,
+                    Ok(Value::Primitive(Primitive::Str(s))) if s.is_empty() => None,
+                    Ok(v) => Some(v),
+                    Err(e) if nested => return Err(e),
+                    Err(e) => return Err(Error::EvaluationFailed(format!("evaluation error: {}", e))),
+                };
+                match value {
+                    Some(v) => write!(out, "{}", v).map_err(write_failed)?,
+                    None => out.write_str(fallback).map_err(write_failed)?,
                 }
-                obj.call(&vals)
+                rest = &after[consumed..];
+                continue;
+            }
+            let ((expr, spans), consumed) = parser::parse_internal(after, parser::Rule::delimited_expr, parser::DEFAULT_MAX_NESTING)?;
+            let result = if nested { self.evaluate(&expr)? } else { self.evaluate_spanned(&expr, &spans, input.len() - after.len())? };
+            write!(out, "{}", result).map_err(write_failed)?;
+            rest = &after[consumed..];
+        }
+        // copy the remainder
+        out.write_str(rest).map_err(write_failed)
+    }
+
+    // `"ab" * 3` is "ababab"
+    fn repeat(&self, s: &str, n: i64) -> Result<Value> {
+        if n < 0 {
+            return Err(Error::EvaluationFailed(format!("cannot repeat a string {} times", n)));
+        }
+        // sized before anything is allocated: a string can be no larger than `isize::MAX` bytes
+        let n = n as usize;
+        if s.len().checked_mul(n).is_none_or(|bytes| bytes > isize::MAX as usize) {
+            return Err(Error::IntegerOverflow(format!("string of {} bytes * {}", s.len(), n)));
+        }
+        self.check_size(s.chars().count() * n)?;
+        Ok(Value::from(s.repeat(n)))
+    }
+
+    // `a < b <= c` is `a < b && b <= c`, with `b` evaluated only once; like `&&`, the chain stops at the first
+    // comparison that is false.
+    fn eval_chain(&self, first: &Expr, rest: &[(BinaryOp, Expr)]) -> Result<Value> {
+        let mut l = self.evaluate(first)?;
+        for (op, right) in rest {
+            let r = self.evaluate(right)?;
+            if !self.compare(*op, &l, &r)? {
+                return Ok(Value::from(false));
             }
-            _ => Err(Error::NotCallable),
+            l = r;
         }
+        Ok(Value::from(true))
+    }
+
+    fn compare(&self, op: BinaryOp, l: &Value, r: &Value) -> Result<bool> {
+        use BinaryOp::*;
+        #[cfg(feature = "decimal")]
+        if let Some(ord) = decimal::compare(l, r) {
+            return Ok(match op {
+                Lt => ord.is_lt(),
+                Le => ord.is_le(),
+                Gt => ord.is_gt(),
+                Ge => ord.is_ge(),
+                _ => unreachable!(),
+            });
+        }
+        // numeric or string comparisons
+        if let (Some(a), Some(b)) = (l.to_float_lossy(), r.to_float_lossy()) {
+            let res = match op {
+                Lt => a < b,
+                Le => a <= b,
+                Gt => a > b,
+                Ge => a >= b,
+                _ => unreachable!(),
+            };
+            return Ok(res);
+        }
+        if let (Value::Primitive(Primitive::Str(a)), Value::Primitive(Primitive::Str(b))) = (l, r) {
+            let ord = if self.natural_string_order { natural_cmp(a, b) } else { a.cmp(b) };
+            let res = match op {
+                Lt => ord.is_lt(),
+                Le => ord.is_le(),
+                Gt => ord.is_gt(),
+                Ge => ord.is_ge(),
+                _ => unreachable!(),
+            };
+            return Ok(res);
+        }
+        // objects may define their own ordering
+        let ord = match (l, r) {
+            (Value::Object(a), _) => a.compare(r),
+            (_, Value::Object(b)) => b.compare(l).map(Ordering::reverse),
+            _ => None,
+        };
+        if let Some(ord) = ord {
+            let res = match op {
+                Lt => ord.is_lt(),
+                Le => ord.is_le(),
+                Gt => ord.is_gt(),
+                Ge => ord.is_ge(),
+                _ => unreachable!(),
+            };
+            return Ok(res);
+        }
+        Err(Error::TypeMismatch("comparison requires two numbers, two strings or comparable objects".into()))
     }
 
     fn eval_binary(&self, op: BinaryOp, left: &Expr, right: &Expr) -> Result<Value> {
@@ -166,49 +715,91 @@ impl<'a, R: VariableResolver> Evaluator<'a, R> {
             Eq => {
                 let l = self.evaluate(left)?;
                 let r = self.evaluate(right)?;
-                Ok(Value::Primitive(Primitive::Bool(l == r)))
+                Ok(Value::Primitive(Primitive::Bool(equals(&l, &r))))
             }
             Ne => {
                 let l = self.evaluate(left)?;
                 let r = self.evaluate(right)?;
-                Ok(Value::Primitive(Primitive::Bool(l != r)))
+                Ok(Value::Primitive(Primitive::Bool(!equals(&l, &r))))
             }
             Lt | Le | Gt | Ge => {
                 let l = self.evaluate(left)?;
                 let r = self.evaluate(right)?;
-                // numeric or string comparisons
-                if let (Some(a), Some(b)) = (l.to_float_lossy(), r.to_float_lossy()) {
-                    let res = match op {
-                        Lt => a < b,
-                        Le => a <= b,
-                        Gt => a > b,
-                        Ge => a >= b,
-                        _ => unreachable!(),
+                Ok(Value::from(self.compare(op, &l, &r)?))
+            }
+            In => {
+                let l = self.evaluate(left)?;
+                let r = self.evaluate(right)?;
+                let found = if let Value::Primitive(Primitive::Str(haystack)) = &r {
+                    let Value::Primitive(Primitive::Str(needle)) = &l else {
+                        return Err(Error::TypeMismatch("'in' a string expects a string".into()));
                     };
-                    return Ok(Value::Primitive(Primitive::Bool(res)));
-                }
-                if let (Value::Primitive(Primitive::Str(a)), Value::Primitive(Primitive::Str(b))) = (&l, &r) {
-                    let res = match op {
-                        Lt => a < b,
-                        Le => a <= b,
-                        Gt => a > b,
-                        Ge => a >= b,
-                        _ => unreachable!(),
+                    haystack.contains(needle.as_str())
+                } else if let Some(items) = list::as_list(&r) {
+                    items.items().contains(&l)
+                } else if let Some(s) = set::as_set(&r) {
+                    s.contains(&l)
+                } else if let Some(range) = range::as_range(&r) {
+                    range.contains(&l)
+                } else if let Some(d) = r.downcast_ref::<dict::DictObject>() {
+                    let Value::Primitive(Primitive::Str(key)) = &l else {
+                        return Err(Error::TypeMismatch("'in' a dict expects a string key".into()));
                     };
-                    return Ok(Value::Primitive(Primitive::Bool(res)));
-                }
-                Err(Error::TypeMismatch("comparison requires two numbers or two strings".into()))
+                    d.entries().contains_key(key)
+                } else if let Value::Object(obj) = &r
+                    && let Some(items) = obj.iter()
+                {
+                    items.contains(&l)
+                } else {
+                    return Err(Error::TypeMismatch(format!("'in' expects a list, set, range, dict or string, got {}", r.type_name())));
+                };
+                Ok(Value::Primitive(Primitive::Bool(found)))
+            }
+            Range => {
+                let l = self.evaluate(left)?;
+                let r = self.evaluate(right)?;
+                let (Value::Primitive(Primitive::Int(start)), Value::Primitive(Primitive::Int(end))) = (&l, &r) else {
+                    return Err(Error::TypeMismatch(format!("'..' expects two ints, got {} and {}", l.type_name(), r.type_name())));
+                };
+                // `toList` has to know the limit before it allocates, as the size is only checked afterwards
+                range::with_limit(*start, *end, self.max_result_size.unwrap_or(range::MAX_LIST_LENGTH))
+            }
+            BitOr | BitXor | BitAnd | Shl | Shr => {
+                let l = self.evaluate(left)?;
+                let r = self.evaluate(right)?;
+                let (Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) = (&l, &r) else {
+                    return Err(Error::TypeMismatch(format!("'{}' expects two ints, got {} and {}", binary_symbol(op), l.type_name(), r.type_name())));
+                };
+                let (a, b) = (*a, *b);
+                let res = match op {
+                    BitOr => a | b,
+                    BitXor => a ^ b,
+                    BitAnd => a & b,
+                    // bits shifted out are lost; `>>` keeps the sign
+                    _ => {
+                        let shift = u32::try_from(b).ok().filter(|s| *s < 64).ok_or_else(|| Error::EvaluationFailed(format!("shift amount must be between 0 and 63, got {}", b)))?;
+                        if op == Shl { a << shift } else { a >> shift }
+                    }
+                };
+                Ok(Value::from(res))
             }
             Add => {
                 let l = self.evaluate(left)?;
                 let r = self.evaluate(right)?;
+                #[cfg(feature = "decimal")]
+                if let Some(res) = decimal::arithmetic(op, &l, &r) {
+                    return res;
+                }
                 match (&l, &r) {
-                    (Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) => Ok(Value::Primitive(Primitive::Int(a + b))),
+                    (Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) => checked_int(op, *a, *b, a.checked_add(*b)),
                     _ => {
                         let (af, bf) = (l.to_float_lossy(), r.to_float_lossy());
                         if let (Some(af), Some(bf)) = (af, bf) {
                             Ok(Value::Primitive(Primitive::Float(af + bf)))
                         } else if let (Value::Primitive(Primitive::Str(as_)), Value::Primitive(Primitive::Str(bs_))) = (&l, &r) {
+                            if self.max_result_size.is_some() {
+                                self.check_size(as_.chars().count() + bs_.chars().count())?;
+                            }
                             Ok(Value::Primitive(Primitive::Str(format!("{}{}", as_, bs_))))
                         } else {
                             Err(Error::TypeMismatch("'+' expects numbers or strings".into()))
@@ -219,27 +810,36 @@ impl<'a, R: VariableResolver> Evaluator<'a, R> {
             Sub | Mul | Div | Mod | Pow => {
                 let l = self.evaluate(left)?;
                 let r = self.evaluate(right)?;
+                #[cfg(feature = "decimal")]
+                if let Some(res) = decimal::arithmetic(op, &l, &r) {
+                    return res;
+                }
                 // Preserve integers for Sub, Mul, Mod if both ints
                 match (op, &l, &r) {
-                    (BinaryOp::Sub, Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) => return Ok(Value::Primitive(Primitive::Int(a - b))),
-                    (BinaryOp::Mul, Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) => return Ok(Value::Primitive(Primitive::Int(a * b))),
+                    (BinaryOp::Mul, Value::Primitive(Primitive::Str(s)), Value::Primitive(Primitive::Int(n))) | (BinaryOp::Mul, Value::Primitive(Primitive::Int(n)), Value::Primitive(Primitive::Str(s))) => {
+                        return self.repeat(s, *n);
+                    }
+                    (BinaryOp::Sub, Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) => return checked_int(op, *a, *b, a.checked_sub(*b)),
+                    (BinaryOp::Mul, Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) => return checked_int(op, *a, *b, a.checked_mul(*b)),
                     (BinaryOp::Mod, Value::Primitive(Primitive::Int(_)), Value::Primitive(Primitive::Int(b))) if *b == 0 => return Err(Error::DivideByZero),
-                    (BinaryOp::Mod, Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) => return Ok(Value::Primitive(Primitive::Int(a % b))),
+                    (BinaryOp::Mod, Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))) => return checked_int(op, *a, *b, a.checked_rem(*b)),
                     _ => {}
                 }
+                // integer division by zero is always an error; float division only when IEEE semantics are off
+                let zero_divisor_fails = !self.ieee_division || matches!((&l, &r), (Value::Primitive(Primitive::Int(_)), Value::Primitive(Primitive::Int(_))));
                 let (af, bf) = (l.to_float_lossy(), r.to_float_lossy());
                 if let (Some(a), Some(b)) = (af, bf) {
                     let res = match op {
                         Sub => a - b,
                         Mul => a * b,
                         Div => {
-                            if b == 0.0 {
+                            if b == 0.0 && zero_divisor_fails {
                                 return Err(Error::DivideByZero);
                             }
                             a / b
                         }
                         Mod => {
-                            if b == 0.0 {
+                            if b == 0.0 && zero_divisor_fails {
                                 return Err(Error::DivideByZero);
                             }
                             a % b
@@ -256,8 +856,280 @@ impl<'a, R: VariableResolver> Evaluator<'a, R> {
     }
 }
 
+// The result of integer arithmetic computed with one of the `checked_*` methods: overflow is an error rather than
+// a panic (debug builds) or a wrapped result (release builds).
+fn checked_int(op: BinaryOp, a: i64, b: i64, result: Option<i64>) -> Result<Value> {
+    result.map(Value::from).ok_or_else(|| Error::IntegerOverflow(format!("{} {} {}", a, binary_symbol(op), b)))
+}
+
+// The position of evaluation in an expression from `parser::parse_spanned`, as an index into its nodes in
+// visiting order, which is also the order of their spans. Nodes are mostly evaluated in that order; the ones that
+// are not (a lambda's body, the untaken side of `&&`) are stepped over once their parent is done, and those with
+// a sibling evaluated after them (the `try` callee, an implicit function argument) are skipped right away.
+struct Cursor {
+    // the number of nodes in the subtree of each node
+    sizes: Vec<usize>,
+    next: Cell<usize>,
+    // the innermost node that failed; errors propagate outwards, so the first node to report one is the innermost
+    failed: Cell<Option<usize>>,
+}
+
+impl Cursor {
+    fn new(expr: &Expr) -> Cursor {
+        struct Sizes(Vec<usize>);
+        impl Visitor for Sizes {
+            fn visit_expr(&mut self, expr: &Expr) {
+                let node = self.0.len();
+                self.0.push(0);
+                walk_expr(self, expr);
+                self.0[node] = self.0.len() - node;
+            }
+        }
+
+        let mut sizes = Sizes(Vec::new());
+        sizes.visit_expr(expr);
+        Cursor {
+            sizes: sizes.0,
+            next: Cell::new(0),
+            failed: Cell::new(None),
+        }
+    }
+
+    fn enter(&self) -> usize {
+        let node = self.next.get();
+        self.next.set(node + 1);
+        node
+    }
+
+    fn leave(&self, node: usize, failed: bool) {
+        if failed && self.failed.get().is_none() {
+            self.failed.set(Some(node));
+        }
+        self.next.set(node + self.sizes.get(node).copied().unwrap_or(1));
+    }
+
+    fn skip(&self) {
+        let node = self.next.get();
+        self.next.set(node + self.sizes.get(node).copied().unwrap_or(1));
+    }
+}
+
+// Names bound during evaluation, by a `let` or by the parameters of a function, in front of the enclosing scope.
+// A function keeps the scope it is created in, as it may well be called after the `let` around it is done.
+struct Scope {
+    names: Vec<String>,
+    values: Vec<Value>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    fn get(&self, name: &str) -> Option<Value> {
+        let mut scope = Some(self);
+        while let Some(s) = scope {
+            if let Some(i) = s.names.iter().position(|n| n == name) {
+                return Some(s.values[i].clone());
+            }
+            scope = s.parent.as_deref();
+        }
+        None
+    }
+}
+
+// The resolver of an evaluator, for the functions created during evaluation to look names up in when they are
+// called. Those functions are values that can outlive the evaluator and the resolver it borrows, so they hold a
+// `Weak` reference; the only strong one belongs to the evaluator, which cannot outlive the resolver. A function
+// called once its evaluator is gone finds no variables.
+struct Root(*const (dyn VariableResolver + 'static));
+
+impl Root {
+    fn new<'r>(resolver: &'r (dyn VariableResolver + 'r)) -> Root {
+        let resolver: *const (dyn VariableResolver + 'r) = resolver;
+        // SAFETY: only the lifetime changes; `RootResolver` dereferences the pointer only while it is valid
+        Root(unsafe { std::mem::transmute::<*const (dyn VariableResolver + 'r), *const (dyn VariableResolver + 'static)>(resolver) })
+    }
+}
+
+struct RootResolver(Weak<Root>);
+
+impl VariableResolver for RootResolver {
+    fn resolve(&self, name: &str) -> Option<Value> {
+        // SAFETY: the root is alive, so is the evaluator holding it and the resolver that evaluator borrows
+        self.0.upgrade().and_then(|root| unsafe { (*root.0).resolve(name) })
+    }
+
+    fn try_resolve(&self, name: &str) -> Result<Option<Value>> {
+        match self.0.upgrade() {
+            // SAFETY: as in `resolve`
+            Some(root) => unsafe { (*root.0).try_resolve(name) },
+            None => Ok(None),
+        }
+    }
+}
+
+// The spans of `folded`, given those of `expr` that it was folded from. Folding replaces whole subtrees, so the
+// trees match until a node of a different kind (or with a different number of children) replaced the original,
+// and every node of the replacement takes the original's span.
+fn folded_spans(expr: &Expr, spans: &[Range<usize>], folded: &Expr, out: &mut Vec<Range<usize>>) {
+    let (before, after) = (children(expr), children(folded));
+    if std::mem::discriminant(expr) != std::mem::discriminant(folded) || before.len() != after.len() {
+        out.extend(std::iter::repeat_n(spans[0].clone(), node_count(folded)));
+        return;
+    }
+    out.push(spans[0].clone());
+    let mut rest = &spans[1..];
+    for (child, folded_child) in before.into_iter().zip(after) {
+        let (own, others) = rest.split_at(node_count(child));
+        folded_spans(child, own, folded_child, out);
+        rest = others;
+    }
+}
+
+// The direct children of `expr`, in the order a `Visitor` reaches them.
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Literal(_) | Expr::Var(_) => vec![],
+        Expr::ListLiteral(items) | Expr::SetLiteral(items) => items.iter().collect(),
+        Expr::DictLiteral(pairs) => pairs.iter().flat_map(|(key, value)| [key, value]).collect(),
+        Expr::Member { object, .. } => vec![object],
+        Expr::Call { callee, args } => std::iter::once(&**callee).chain(args).collect(),
+        Expr::Compare { first, rest } => std::iter::once(&**first).chain(rest.iter().map(|(_, e)| e)).collect(),
+        Expr::Unary { expr, .. } | Expr::Lambda { body: expr, .. } => vec![expr],
+        Expr::Index { object: left, index: right } | Expr::Binary { left, right, .. } | Expr::Let { value: left, body: right, .. } | Expr::Assign { object: left, value: right, .. } => vec![left, right],
+    }
+}
+
+fn node_count(expr: &Expr) -> usize {
+    struct Count(usize);
+    impl Visitor for Count {
+        fn visit_expr(&mut self, expr: &Expr) {
+            self.0 += 1;
+            walk_expr(self, expr);
+        }
+    }
+
+    let mut count = Count(0);
+    count.visit_expr(expr);
+    count.0
+}
+
+// Whether a method argument outside of any implicit function becomes one: it uses `@`, anywhere within it, and is
+// not just `@`.
+pub(crate) fn is_implicit_function(arg: &Expr) -> bool {
+    if matches!(arg, Expr::Var(name) if name == parser::CURRENT) || matches!(arg, Expr::Lambda { .. }) {
+        return false;
+    }
+    let mut finder = CurrentFinder(false);
+    finder.visit_expr(arg);
+    finder.0
+}
+
+struct CurrentFinder(bool);
+
+impl Visitor for CurrentFinder {
+    fn visit_var(&mut self, name: &str) {
+        self.0 |= name == parser::CURRENT;
+    }
+}
+
+struct PartialEvaluator<'e, 'a, R: VariableResolver> {
+    evaluator: &'e Evaluator<'a, R>,
+}
+
+impl<R: VariableResolver> VisitorMut for PartialEvaluator<'_, '_, R> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        // lambda parameters and let names shadow the resolver, so the bodies that see them are left alone. The
+        // target of an assignment must stay the object it names rather than become a copy of its value.
+        match expr {
+            Expr::Lambda { .. } => return,
+            Expr::Let { value, .. } | Expr::Assign { value, .. } => return self.visit_expr_mut(value),
+            _ => {}
+        }
+        if path_root(expr).is_some_and(|root| root != parser::CURRENT)
+            && let Some(known) = self.evaluator.eval_path(expr).ok().as_ref().and_then(value_to_expr)
+        {
+            *expr = known;
+            return;
+        }
+        walk_expr_mut(self, expr);
+        if !matches!(expr, Expr::Literal(_))
+            && expr.is_constant()
+            && let Some(folded) = self.evaluator.evaluate(expr).ok().as_ref().and_then(value_to_expr)
+        {
+            *expr = folded;
+        }
+    }
+}
+
+// `==`: a decimal equals an int of the same value, as it does for `<=` and `>=`
+fn equals(l: &Value, r: &Value) -> bool {
+    #[cfg(feature = "decimal")]
+    if let Some(ord) = decimal::compare(l, r) {
+        return ord.is_eq();
+    }
+    l == r
+}
+
+// The literal form of a value, if it has one. Functions and custom objects do not, nor do non-finite floats.
+fn value_to_expr(v: &Value) -> Option<Expr> {
+    match v {
+        Value::Primitive(Primitive::Float(f)) if !f.is_finite() => None,
+        Value::Primitive(p) => Some(Expr::Literal(p.clone())),
+        _ => {
+            if let Some(l) = list::as_list(v) {
+                l.items().iter().map(value_to_expr).collect::<Option<Vec<_>>>().map(Expr::ListLiteral)
+            } else if let Some(d) = v.downcast_ref::<dict::DictObject>() {
+                let pairs = d.entries().iter().map(|(k, v)| value_to_expr(v).map(|v| (Expr::Literal(Primitive::Str(k.clone())), v)));
+                pairs.collect::<Option<Vec<_>>>().map(Expr::DictLiteral)
+            } else if let Some(s) = set::as_set(v)
+                && !s.items().is_empty()
+            {
+                // `{}` would be an empty dict, so the empty set has no literal
+                s.items().iter().map(|p| value_to_expr(&Value::Primitive(p.clone()))).collect::<Option<Vec<_>>>().map(Expr::SetLiteral)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// Size of a value for `max_result_size`: characters of a string, elements of a list or dict, zero otherwise.
+fn value_size(v: &Value) -> usize {
+    match v {
+        Value::Primitive(Primitive::Str(s)) => s.chars().count(),
+        _ => {
+            if let Some(l) = v.downcast_ref::<list::ListObject>() {
+                l.items().len()
+            } else if let Some(d) = v.downcast_ref::<dict::DictObject>() {
+                d.entries().len()
+            } else if let Some(s) = set::as_set(v) {
+                s.items().len()
+            } else {
+                0
+            }
+        }
+    }
+}
+
+// The variable a dotted path such as `a.b.c` starts at, if `expr` is one.
+fn path_root(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Var(name) => Some(name),
+        Expr::Member { object, .. } => path_root(object),
+        _ => None,
+    }
+}
+
+// Whether `name` is the dotted path `expr` spells, without putting the path together.
+fn names_path(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Var(var) => var == name,
+        Expr::Member { object, field } => name.strip_suffix(field.as_str()).and_then(|base| base.strip_suffix('.')).is_some_and(|base| names_path(object, base)),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use std::any::Any;
     use std::rc::Rc;
@@ -337,24 +1209,34 @@ mod tests {
         }
     }
 
+    fn eval<R: VariableResolver>(ev: &Evaluator<R>, src: &str) -> Result<Value> {
+        ev.evaluate(&parser::parse_expression(src).unwrap())
+    }
+
+    // `src` evaluated without variables, as it prints, for the tests of the other modules
+    #[cfg(any(feature = "chrono", feature = "decimal"))]
+    pub(crate) fn eval_to_string(src: &str) -> String {
+        evaluate(src, &NoVars).unwrap_or_else(|e| panic!("'{}' failed: {}", src, e)).to_string()
+    }
+
     #[test]
     fn eval_basic_expressions() {
         let resolver = MockResolver::new();
         let ev = Evaluator::new(&resolver);
-        assert_eq!(ev.evaluate(&parser::parse_expression("1").unwrap()).unwrap(), Value::from(1i64));
-        assert_eq!(ev.evaluate(&parser::parse_expression("1").unwrap()).unwrap().to_string(), "1");
-        assert_eq!(ev.evaluate(&parser::parse_expression("true").unwrap()).unwrap(), Value::from(true));
-        assert_eq!(ev.evaluate(&parser::parse_expression("true || false").unwrap()).unwrap(), Value::from(true));
-        assert_eq!(ev.evaluate(&parser::parse_expression("true && false").unwrap()).unwrap(), Value::from(false));
+        assert_eq!(eval(&ev, "1").unwrap(), Value::from(1i64));
+        assert_eq!(eval(&ev, "1").unwrap().to_string(), "1");
+        assert_eq!(eval(&ev, "true").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "true || false").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "true && false").unwrap(), Value::from(false));
     }
 
     #[test]
     fn eval_literals_and_ops() {
         let resolver = MockResolver::new();
         let ev = Evaluator::new(&resolver);
-        assert_eq!(ev.evaluate(&parser::parse_expression("1 + 2 * 3").unwrap()).unwrap(), Value::from(7i64));
-        assert_eq!(ev.evaluate(&parser::parse_expression("true && !false").unwrap()).unwrap(), Value::from(true));
-        match ev.evaluate(&parser::parse_expression("1/0").unwrap()) {
+        assert_eq!(eval(&ev, "1 + 2 * 3").unwrap(), Value::from(7i64));
+        assert_eq!(eval(&ev, "true && !false").unwrap(), Value::from(true));
+        match eval(&ev, "1/0") {
             Err(Error::DivideByZero) => (),
             other => panic!("expected div by zero, got {:?}", other),
         }
@@ -364,9 +1246,9 @@ mod tests {
     fn eval_paths_and_calls() {
         let resolver = MockResolver::new();
         let ev = Evaluator::new(&resolver);
-        assert_eq!(ev.evaluate(&parser::parse_expression("x").unwrap()).unwrap(), Value::from(10i64));
-        assert_eq!(ev.evaluate(&parser::parse_expression("truth || false").unwrap()).unwrap(), Value::from(true));
-        let v = ev.evaluate(&parser::parse_expression("add(2, 3)").unwrap()).unwrap();
+        assert_eq!(eval(&ev, "x").unwrap(), Value::from(10i64));
+        assert_eq!(eval(&ev, "truth || false").unwrap(), Value::from(true));
+        let v = eval(&ev, "add(2, 3)").unwrap();
         match v {
             Value::Primitive(Primitive::Float(f)) => assert!((f - 5.0).abs() < 1e-9),
             _ => panic!("expected float"),
@@ -405,34 +1287,1017 @@ mod tests {
     }
 
     #[test]
-    fn eval_lists_and_indexing() {
+    fn eval_registered_functions() {
         let resolver = MockResolver::new();
-        let ev = Evaluator::new(&resolver);
-        // [10, 20, 30][1] => 20
-        assert_eq!(ev.evaluate(&parser::parse_expression("[10, 20, 30][1]").unwrap()).unwrap(), Value::from(20i64));
-        // [10][1] => IndexOutOfBounds
-        match ev.evaluate(&parser::parse_expression("[10][1]").unwrap()) {
-            Err(Error::IndexOutOfBounds { index, len }) => {
-                assert_eq!(index, 1);
-                assert_eq!(len, 1);
-            }
-            other => panic!("expected IndexOutOfBounds, got {:?}", other),
-        }
+        let mut ev = Evaluator::new(&resolver);
+        ev.register("greet", |args: &[Value]| Ok(Value::from(format!("hello {}", args[0]))));
+        assert_eq!(eval(&ev, "greet('bob')").unwrap(), Value::from("hello bob"));
+        // registered functions shadow resolver variables of the same name
+        ev.register("x", |_args: &[Value]| Ok(Value::from(1i64)));
+        assert_eq!(eval(&ev, "x()").unwrap(), Value::from(1i64));
+        assert_eq!(eval(&ev, "truth").unwrap(), Value::from(true));
+        // a helper that errors on bad input, used from a larger expression
+        ev.register("max2", |args: &[Value]| match args {
+            [Value::Primitive(Primitive::Int(a)), Value::Primitive(Primitive::Int(b))] => Ok(Value::from(*a.max(b))),
+            _ => Err(Error::TypeMismatch("max2 expects two ints".into())),
+        });
+        assert_eq!(eval(&ev, "max2(3, x()) + max2(-1, -5)").unwrap(), Value::from(2i64));
+        assert!(matches!(eval(&ev, "max2(1)"), Err(Error::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn eval_list_map_and_flat_map() {
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        ev.register("double", |args: &[Value]| Ok(Value::from(args[0].to_float_lossy().unwrap() * 2.0)));
+        ev.register("twice", |args: &[Value]| Ok(list::new(vec![args[0].clone(), args[0].clone()])));
+        ev.register("ident", |args: &[Value]| Ok(args[0].clone()));
+
+        assert_eq!(eval(&ev, "[1, 2, 3].map(double)").unwrap().to_string(), "[2, 4, 6]");
+        assert_eq!(eval(&ev, "[].map(double)").unwrap().to_string(), "[]");
+        assert_eq!(eval(&ev, "[1, 2].flatMap(twice)").unwrap().to_string(), "[1, 1, 2, 2]");
+        assert_eq!(eval(&ev, "[[1], [], [2, [3]]].flatMap(ident)").unwrap().to_string(), "[1, 2, [3]]");
+        assert!(matches!(eval(&ev, "[1, 2].flatMap(double)"), Err(Error::TypeMismatch(_))));
+        assert!(matches!(eval(&ev, "[1, 2].map(1)"), Err(Error::NotCallable)));
+    }
+
+    #[test]
+    fn eval_list_filter() {
+        struct Preds;
+        impl VariableResolver for Preds {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                match name {
+                    "isEven" => Some(function::method1(|v: &Value| Ok(Value::from(matches!(v, Value::Primitive(Primitive::Int(i)) if i % 2 == 0))))),
+                    "asIs" => Some(function::method1(|v: &Value| Ok(v.clone()))),
+                    _ => None,
+                }
+            }
+        }
+        let ev = Evaluator::new(&Preds);
+
+        assert_eq!(eval(&ev, "[5, 4, 1, 2, 8].filter(isEven)").unwrap().to_string(), "[4, 2, 8]");
+        assert_eq!(eval(&ev, "[1, 3].filter(isEven)").unwrap().to_string(), "[]");
+        // the result is coerced to a bool
+        assert_eq!(eval(&ev, "[0, 1, 2, [], [0]].filter(asIs)").unwrap().to_string(), "[1, 2, [0]]");
+        assert!(matches!(eval(&ev, "['yes', 'true'].filter(asIs)"), Err(Error::TypeMismatch(_))));
+        assert_eq!(eval(&ev, "[1, 2, 3, 4].filter(@ > 2)").unwrap().to_string(), "[3, 4]");
+    }
+
+    #[test]
+    fn eval_list_first_last_of_empty() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        assert!(matches!(eval(&ev, "[].first()"), Err(Error::IndexOutOfBounds { index: 0, len: 0 })));
+        assert!(matches!(eval(&ev, "[].last()"), Err(Error::IndexOutOfBounds { index: 0, len: 0 })));
+        assert_eq!(eval(&ev, "[1, 2].reverse().first()").unwrap(), Value::from(2i64));
+    }
+
+    #[test]
+    fn eval_dict_updates_leave_inputs_unchanged() {
+        struct Dicts(Value, Value);
+        impl VariableResolver for Dicts {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                match name {
+                    "defaults" => Some(self.0.clone()),
+                    "overrides" => Some(self.1.clone()),
+                    _ => None,
+                }
+            }
+        }
+        let parse = |src: &str| eval(&Evaluator::new(&MockResolver::new()), src).unwrap();
+        let vars = Dicts(parse("{'host': 'localhost', 'port': 80}"), parse("{'port': 8080}"));
+        let ev = Evaluator::new(&vars);
+
+        assert_eq!(eval(&ev, "defaults.merge(overrides)").unwrap().to_string(), "{host: localhost, port: 8080}");
+        assert_eq!(eval(&ev, "overrides.merge(defaults)").unwrap().to_string(), "{host: localhost, port: 80}");
+        assert_eq!(eval(&ev, "defaults").unwrap().to_string(), "{host: localhost, port: 80}");
+        assert_eq!(eval(&ev, "overrides").unwrap().to_string(), "{port: 8080}");
+
+        assert_eq!(eval(&ev, "defaults.set('tls', true).set('port', 443).remove('host')").unwrap().to_string(), "{port: 443, tls: true}");
+        assert_eq!(eval(&ev, "defaults").unwrap().to_string(), "{host: localhost, port: 80}");
+    }
+
+    #[test]
+    fn eval_dict_map_values() {
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        ev.register("double", |args: &[Value]| match &args[0] {
+            Value::Primitive(Primitive::Int(i)) => Ok(Value::from(i * 2)),
+            other => Err(Error::TypeMismatch(format!("cannot double {}", other.type_name()))),
+        });
+
+        assert_eq!(eval(&ev, "{'a': 1, 'b': 2, 'c': x}.mapValues(double)").unwrap().to_string(), "{a: 2, b: 4, c: 20}");
+        assert!(matches!(eval(&ev, "{'a': 1, 'b': 'two'}.mapValues(double)"), Err(Error::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn eval_implicit_current() {
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        ev.register("sq", |args: &[Value]| Ok(Value::from(args[0].to_float_lossy().unwrap().powi(2))));
+
+        assert_eq!(eval(&ev, "[1, 2, 3].map(@ * 2)").unwrap().to_string(), "[2, 4, 6]");
+        assert_eq!(eval(&ev, "['a', 'bb'].map(@.length)").unwrap().to_string(), "[1, 2]");
+        // other variables, dotted names and registered functions are available inside
+        assert_eq!(eval(&ev, "[1, 2].map(@ * x)").unwrap().to_string(), "[10, 20]");
+        assert_eq!(eval(&ev, "[1, 2].map(math.add(@, 1))").unwrap().to_string(), "[2, 3]");
+        assert_eq!(eval(&ev, "[1, 2].map(sq(@) + 1)").unwrap().to_string(), "[2, 5]");
+        // only the outermost argument is a function: inside it, method arguments that use `@` are evaluated
+        assert_eq!(eval(&ev, "['ab', 'xyz'].map(@.substring(0, @.length - 1))").unwrap().to_string(), "[a, xy]");
+        assert_eq!(eval(&ev, "[1, 2].map(math.add(@ * 2, 1))").unwrap().to_string(), "[3, 5]");
+        assert_eq!(eval(&ev, "[[1, 2], [3]].map(@.map(|n| n * @.length))").unwrap().to_string(), "[[2, 4], [3]]");
+        assert!(matches!(eval(&ev, "[[1, 2], [3]].map(@.map(@ * 10))").map(|v| v.to_string()), Err(Error::TypeMismatch(_))));
+        assert_eq!(eval(&ev, "['a', 'b'].map(['b'].contains(@))").unwrap().to_string(), "[false, true]");
+        assert_eq!(eval(&ev, "[1, 2].map(|n| [3].map(@ + n))").unwrap().to_string(), "[[4], [5]]");
+        assert_eq!(eval(&ev, "[1, 2, 3].groupBy(@ % 2 == 0)").unwrap().to_string(), "{false: [1, 3], true: [2]}");
+        assert_eq!(eval(&ev, "{'a': 1, 'b': 2}.filter(@ != 'a')").unwrap().to_string(), "{b: 2}");
+        assert_eq!(eval(&ev, "[1, 2, 3].flatMap([@, @])").unwrap().to_string(), "[1, 1, 2, 2, 3, 3]");
+        // outside of a method argument `@` is just an unresolvable variable
+        assert!(matches!(eval(&ev, "@ + 1").map(|v| v.to_string()), Err(Error::ResolveFailed(_))));
+        assert!(matches!(eval(&ev, "[1].map(missing + @)").map(|v| v.to_string()), Err(Error::ResolveFailed(_))));
+        let lenient = Evaluator::new(&resolver).lenient_vars(true);
+        assert_eq!(eval(&lenient, "[1].map(missing == @)").unwrap().to_string(), "[false]");
+    }
+
+    #[test]
+    fn eval_lambdas() {
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        ev.register("y", |_: &[Value]| Ok(Value::from("function")));
+
+        assert_eq!(eval(&ev, "[1, 2, 3].map(|x| x + 1)").unwrap().to_string(), "[2, 3, 4]");
+        assert_eq!(eval(&ev, "[1, 2, 3, 4].filter(|n| n % 2 == 0)").unwrap().to_string(), "[2, 4]");
+        assert_eq!(eval(&ev, "[1, 2, 3].reduce(|acc, n| acc + n, 0)").unwrap().to_string(), "6");
+        assert_eq!(eval(&ev, "['a', 'b'].reduce(|acc, s| acc + s.toUpper(), '>')").unwrap().to_string(), ">AB");
+        assert_eq!(eval(&ev, "{'a': 1, 'b': 2}.filter(|k, v| v > 1)").unwrap().to_string(), "{b: 2}");
+        assert_eq!(eval(&ev, "(|a, b| a * b)(3, 4)").unwrap().to_string(), "12");
+        assert_eq!(eval(&ev, "(|| 42)()").unwrap().to_string(), "42");
+        // variables, parameters shadowing variables and functions, nested lambdas and `@`
+        assert_eq!(eval(&ev, "[1, 2].map(|n| n * x)").unwrap().to_string(), "[10, 20]");
+        assert_eq!(eval(&ev, "[1, 2].map(|x| x + 1)").unwrap().to_string(), "[2, 3]");
+        assert_eq!(eval(&ev, "[1, 2].map(|y| y)").unwrap().to_string(), "[1, 2]");
+        assert_eq!(eval(&ev, "(|a| |b| a - b)(10)(3)").unwrap().to_string(), "7");
+        assert_eq!(eval(&ev, "[[1, 2], [3]].map(@.map(|n| n + @.length))").unwrap().to_string(), "[[3, 4], [4]]");
+        // a lambda argument is the function itself, not an implicit function
+        assert_eq!(eval(&ev, "[1, 2].map(|n| [n, @])").map(|v| v.to_string()).unwrap_err().to_string(), Error::ResolveFailed("@".into()).to_string());
+        assert!(matches!(eval(&ev, "(|a, b| a)(1)").map(|v| v.to_string()), Err(Error::EvaluationFailed(_))));
+        assert!(matches!(eval(&ev, "[1].reduce(|acc| acc, 0)").map(|v| v.to_string()), Err(Error::EvaluationFailed(_))));
+        assert_eq!(eval(&ev, "[].reduce(|acc, n| acc + n, 'empty')").unwrap().to_string(), "empty");
+        assert_eq!(eval(&ev, "type(|x| x)").unwrap().to_string(), "function");
+        // names are looked up when a call needs them, in the scope the function was created in
+        assert_eq!(eval(&ev, "[1, 2].map(let k = 3 in |n| n + k)").unwrap().to_string(), "[4, 5]");
+        assert_eq!(eval(&ev, "[1, 2].map(|n| n > 0 || missing)").unwrap().to_string(), "[true, true]");
+        // so a function that outlives its evaluator no longer sees the resolver's variables
+        let add_x = eval(&Evaluator::new(&MockResolver::new()), "|n| n + x").unwrap();
+        assert!(matches!(add_x.call(&[Value::from(1i64)]), Err(Error::ResolveFailed(name)) if name == "x"));
+    }
+
+    #[test]
+    fn eval_let() {
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        ev.register("sq", |args: &[Value]| Ok(Value::from(args[0].to_float_lossy().unwrap().powi(2))));
+
+        assert_eq!(eval(&ev, "let a = 2 in a * a == 4").unwrap().to_string(), "true");
+        assert_eq!(eval(&ev, "let a = x + 1 in a * a").unwrap().to_string(), "121");
+        // nested lets see the outer bindings, and an inner binding shadows an outer one, a variable or a function
+        assert_eq!(eval(&ev, "let a = 1 in let b = a + 1 in a + b").unwrap().to_string(), "3");
+        assert_eq!(eval(&ev, "let a = 1 in let a = a + 10 in a").unwrap().to_string(), "11");
+        assert_eq!(eval(&ev, "let x = 'shadowed' in x").unwrap().to_string(), "shadowed");
+        assert_eq!(eval(&ev, "let sq = 3 in sq + 1").unwrap().to_string(), "4");
+        assert_eq!(eval(&ev, "(let x = 1 in x) + x").unwrap().to_string(), "11");
+        // the value stops at `in`, so a membership test there is parenthesized
+        assert_eq!(eval(&ev, "let found = (2 in [1, 2]) in !found").unwrap().to_string(), "false");
+        assert_eq!(eval(&ev, "let xs = [1, 2] in 2 in xs").unwrap().to_string(), "true");
+        // so does the body of a lambda or a `let` bound as the value
+        assert_eq!(eval(&ev, "let f = |x| x + 1 in f(2)").unwrap().to_string(), "3");
+        assert_eq!(eval(&ev, "let f = |v| v in f(2)").unwrap().to_string(), "2");
+        assert_eq!(eval(&ev, "let a = let b = 1 in b in a").unwrap().to_string(), "1");
+        // bindings are visible to lambdas, implicit functions and dotted names
+        assert_eq!(eval(&ev, "let k = 3 in [1, 2].map(@ * k)").unwrap().to_string(), "[3, 6]");
+        assert_eq!(eval(&ev, "let k = 3 in [1, 2].map(|n| n + k)").unwrap().to_string(), "[4, 5]");
+        assert_eq!(eval(&ev, "let p = {'name': 'ann'} in p['name'].toUpper()").unwrap().to_string(), "ANN");
+        assert_eq!(eval(&ev, "let one = 1 in math.add(one, one)").unwrap().to_string(), "2");
+        // `let` is still usable as a variable name
+        assert!(matches!(eval(&ev, "let + 1").map(|v| v.to_string()), Err(Error::ResolveFailed(name)) if name == "let"));
+        assert!(matches!(eval(&ev, "let a = missing in 1").map(|v| v.to_string()), Err(Error::ResolveFailed(_))));
+    }
+
+    #[test]
+    fn eval_assignment() {
+        use std::cell::RefCell;
+
+        // records every assignment; `port` reads back the last one
+        struct Settings(RefCell<Vec<(String, Value)>>);
+        impl Object for Settings {
+            fn get_member(&self, name: &str) -> Result<Value> {
+                let sets = self.0.borrow();
+                match sets.iter().rev().find(|(field, _)| field == name) {
+                    Some((_, value)) => Ok(value.clone()),
+                    None => Ok(Value::Primitive(Primitive::Null)),
+                }
+            }
+            fn set_member(&self, name: &str, value: Value) -> Result<()> {
+                if name == "readonly" {
+                    return Err(Error::NotAssignable {
+                        type_name: "settings".into(),
+                        member: name.into(),
+                    });
+                }
+                self.0.borrow_mut().push((name.to_string(), value));
+                Ok(())
+            }
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+
+        struct App(Rc<Settings>);
+        impl VariableResolver for App {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                match name {
+                    "settings" => Some(Value::Object(self.0.clone())),
+                    "x" => Some(Value::from(10i64)),
+                    "global" => MockResolver::new().resolve(name),
+                    _ => None,
+                }
+            }
+        }
+
+        let settings = Rc::new(Settings(RefCell::new(Vec::new())));
+        let app = App(settings.clone());
+        let ev = Evaluator::new(&app);
+
+        // the assignment evaluates to the assigned value
+        assert_eq!(eval(&ev, "settings.port = x * 800").unwrap().to_string(), "8000");
+        assert_eq!(eval(&ev, "settings.port").unwrap().to_string(), "8000");
+        assert_eq!(eval(&ev, "settings.name = 'app'").unwrap().to_string(), "app");
+        assert_eq!(settings.0.borrow().iter().map(|(field, value)| format!("{}={}", field, value)).collect::<Vec<_>>(), vec!["port=8000", "name=app"]);
+        // the value is evaluated before anything is assigned
+        assert!(matches!(eval(&ev, "settings.port = missing").map(|v| v.to_string()), Err(Error::ResolveFailed(_))));
+        assert!(matches!(eval(&ev, "settings.readonly = 1").map(|v| v.to_string()), Err(Error::NotAssignable { .. })));
+        assert_eq!(settings.0.borrow().len(), 2);
+        // other objects and primitives are read-only
+        assert!(matches!(eval(&ev, "global.a = 1").map(|v| v.to_string()), Err(Error::NotAssignable { .. })));
+        assert!(matches!(eval(&ev, "[1].length = 3").map(|v| v.to_string()), Err(Error::NotAssignable { .. })));
+        assert!(matches!(eval(&ev, "'s'.length = 3").map(|v| v.to_string()), Err(Error::NotAssignable { type_name, .. }) if type_name == "string"));
+        assert!(matches!(eval(&ev, "{}.k = 3").map(|v| v.to_string()), Err(Error::NotAssignable { .. })));
+        // partial evaluation keeps the target
+        let partial = ev.partial_evaluate(&parser::parse_expression("settings.port = x + 1").unwrap());
+        assert_eq!(partial.to_source(), "settings.port = 11");
+    }
+
+    #[test]
+    fn eval_iterable_object() {
+        // a result set that is not a list, but iterates over its rows
+        struct Rows;
+        impl Object for Rows {
+            fn type_name(&self) -> &'static str {
+                "rows"
+            }
+            fn get_member(&self, name: &str) -> Result<Value> {
+                match name {
+                    "first" => Ok(Value::from("own first")),
+                    _ => Err(Error::UnknownMember {
+                        type_name: "rows".into(),
+                        member: name.into(),
+                    }),
+                }
+            }
+            fn iter(&self) -> Option<Vec<Value>> {
+                Some(vec![Value::from(1i64), Value::from(2i64), Value::from(3i64)])
+            }
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+        // an object that does not iterate, and knows no members at all
+        struct Opaque;
+        impl Object for Opaque {
+            fn type_name(&self) -> &'static str {
+                "opaque"
+            }
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+        struct Db;
+        impl VariableResolver for Db {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                match name {
+                    "rows" => Some(Value::Object(Rc::new(Rows))),
+                    "opaque" => Some(Value::Object(Rc::new(Opaque))),
+                    _ => None,
+                }
+            }
+        }
+
+        let ev = Evaluator::new(&Db);
+        assert_eq!(eval(&ev, "rows.contains(2)").unwrap().to_string(), "true");
+        assert_eq!(eval(&ev, "rows.contains(4)").unwrap().to_string(), "false");
+        assert_eq!(eval(&ev, "rows.map(@ * 10)").unwrap().to_string(), "[10, 20, 30]");
+        assert_eq!(eval(&ev, "rows.filter(|r| r > 1).length").unwrap().to_string(), "2");
+        assert_eq!(eval(&ev, "3 in rows").unwrap().to_string(), "true");
+        // the object's own members come first, and it is still not a list
+        assert_eq!(eval(&ev, "rows.first").unwrap().to_string(), "own first");
+        assert_eq!(eval(&ev, "type(rows)").unwrap().to_string(), "rows");
+        assert!(matches!(eval(&ev, "rows.nope").map(|v| v.to_string()), Err(Error::UnknownMember { type_name, .. }) if type_name == "rows"));
+        // objects that do not iterate get no list members
+        assert!(matches!(eval(&ev, "opaque.contains(1)").map(|v| v.to_string()), Err(Error::UnknownMember { type_name, member }) if type_name == "opaque" && member == "contains"));
+        assert!(matches!(eval(&ev, "1 in opaque").map(|v| v.to_string()), Err(Error::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn eval_list_group_by() {
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        ev.register("parity", |args: &[Value]| Ok(Value::from(if args[0].to_float_lossy().unwrap() as i64 % 2 == 0 { "even" } else { "odd" })));
+        ev.register("len", |args: &[Value]| args[0].get_member("length"));
+
+        assert_eq!(eval(&ev, "[1, 2, 3, 4, 5].groupBy(parity)").unwrap().to_string(), "{even: [2, 4], odd: [1, 3, 5]}");
+        // non-string keys are stringified
+        assert_eq!(eval(&ev, "['bb', 'a', 'cc', 'd'].groupBy(len)").unwrap().to_string(), "{1: [a, d], 2: [bb, cc]}");
+        assert_eq!(eval(&ev, "['bb', 'a'].groupBy(len)['2'][0]").unwrap(), Value::from("bb"));
+        assert_eq!(eval(&ev, "[].groupBy(len)").unwrap().to_string(), "{}");
+    }
+
+    #[test]
+    fn eval_dict_filter() {
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = Rc::clone(&seen);
+        ev.register("positive", move |args: &[Value]| {
+            log.borrow_mut().push(args[0].to_string());
+            Ok(Value::from(args[1].to_float_lossy().unwrap() > 0.0))
+        });
+        ev.register("keyed", |args: &[Value]| Ok(args[0].clone()));
+
+        assert_eq!(eval(&ev, "{'b': -1, 'c': 3, 'a': 2}.filter(positive)").unwrap().to_string(), "{a: 2, c: 3}");
+        assert_eq!(*seen.borrow(), vec!["a", "b", "c"]);
+        assert_eq!(eval(&ev, "{}.filter(positive)").unwrap().to_string(), "{}");
+        // the key is passed as a string, so "true"/"false" keys coerce and other keys do not
+        assert_eq!(eval(&ev, "{'true': 1, 'false': 2}.filter(keyed)").unwrap().to_string(), "{true: 1}");
+        assert!(matches!(eval(&ev, "{'k': 1}.filter(keyed)"), Err(Error::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn eval_downcast_result() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+
+        // a custom object handed out by the resolver comes back out as its concrete type
+        let global = eval(&ev, "global").unwrap();
+        assert!(global.downcast_ref::<MockGlobal>().is_some());
+        assert!(global.downcast_ref::<list::ListObject>().is_none());
+
+        assert!(Value::from(1i64).downcast_ref::<MockGlobal>().is_none());
+        assert_eq!(eval(&ev, "[1, 2, 3]").unwrap().downcast_ref::<list::ListObject>().unwrap().items().len(), 3);
+    }
+
+    #[test]
+    fn eval_partial() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        let partial = |src: &str| ev.partial_evaluate(&parser::parse_expression(src).unwrap()).to_source();
+
+        assert_eq!(partial("x + y"), "10 + y");
+        assert_eq!(partial("x * 3 + y"), "30 + y");
+        assert_eq!(partial("y + x * 3"), "y + 30");
+        assert_eq!(partial("truth && y || 'a'.toUpper() == z"), "true && y || \"A\" == z");
+        assert_eq!(partial("[x, y, 'ab'.length]"), "[10, y, 2]");
+        assert_eq!(partial("(1 + 2) * 3"), "9");
+        // functions and objects stay as references; calls to them are not folded
+        assert_eq!(partial("add(x, 1) + math.add(1, 2)"), "add(10, 1) + math.add(1, 2)");
+        assert_eq!(partial("global[0] + global.a + missing.b"), "global[0] + \"a\" + missing.b");
+        // errors are left for the final evaluation, which may never reach them
+        assert_eq!(partial("y && 1 / 0"), "y && 1 / 0");
+        // implicit functions keep their `@`
+        assert_eq!(partial("ys.map(@ * x)"), "ys.map(@ * 10)");
+        // lenient mode does not turn unknown variables into null
+        let lenient = Evaluator::new(&resolver).lenient_vars(true);
+        assert_eq!(lenient.partial_evaluate(&parser::parse_expression("x + y").unwrap()).to_source(), "10 + y");
+
+        // the folded expression evaluates like the original once the rest is known
+        let folded = ev.partial_evaluate(&parser::parse_expression("x * 2 + (1 + 2)").unwrap());
+        assert!(folded.is_constant());
+        assert_eq!(ev.evaluate(&folded).unwrap(), Value::from(23i64));
+    }
+
+    #[test]
+    fn eval_comparison_chains() {
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        ev.register("five", move |_: &[Value]| {
+            counter.set(counter.get() + 1);
+            Ok(Value::from(5i64))
+        });
+
+        assert_eq!(eval(&ev, "1 < x < 11").unwrap().to_string(), "true");
+        assert_eq!(eval(&ev, "1 < x < 10").unwrap().to_string(), "false");
+        assert_eq!(eval(&ev, "1 <= x <= 10").unwrap().to_string(), "true");
+        assert_eq!(eval(&ev, "0 < 5 <= x < 20").unwrap().to_string(), "true");
+        assert_eq!(eval(&ev, "0 < 5 <= x < 10").unwrap().to_string(), "false");
+        assert_eq!(eval(&ev, "20 > x >= 10 > 9.5").unwrap().to_string(), "true");
+        // the middle operand is evaluated once
+        assert_eq!(eval(&ev, "1 < five() < x").unwrap().to_string(), "true");
+        assert_eq!(calls.get(), 1);
+        // a false link stops the chain, like `&&`
+        assert_eq!(eval(&ev, "x < 1 < missing").unwrap().to_string(), "false");
+        assert!(matches!(eval(&ev, "1 < x < missing").map(|v| v.to_string()), Err(Error::ResolveFailed(_))));
+        // other operators bind as before
+        assert_eq!(eval(&ev, "1 < 2 == 2 < 3").unwrap().to_string(), "true");
+        assert!(matches!(eval(&ev, "1 < 2 < 'a'").map(|v| v.to_string()), Err(Error::TypeMismatch(_))));
+        // parentheses end a chain, leaving a bool to compare
+        assert!(matches!(eval(&ev, "(1 < 2) < 3").map(|v| v.to_string()), Err(Error::TypeMismatch(_))));
+        assert!(matches!(eval(&ev, "1 < (2 < 3)").map(|v| v.to_string()), Err(Error::TypeMismatch(_))));
+        assert_eq!(eval(&ev, "(1 < x < 11) == (x > 1)").unwrap().to_string(), "true");
+        // partial evaluation keeps the chain
+        assert_eq!(ev.partial_evaluate(&parser::parse_expression("1 < 2 < y").unwrap()).to_source(), "1 < 2 < y");
+        assert_eq!(ev.partial_evaluate(&parser::parse_expression("0 < 1 + 1 < y").unwrap()).to_source(), "0 < 2 < y");
+        assert_eq!(ev.partial_evaluate(&parser::parse_expression("y < 1 < x").unwrap()).to_source(), "y < 1 < 10");
+        assert_eq!(ev.partial_evaluate(&parser::parse_expression("1 < 2 < 3").unwrap()).to_source(), "true");
+    }
+
+    #[test]
+    fn eval_access_shares_objects() {
+        // element access hands out the stored object itself, not a copy
+        struct Data(Value);
+        impl VariableResolver for Data {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                (name == "data").then(|| self.0.clone())
+            }
+        }
+
+        let row = dict::new([("id".to_string(), Value::from(1i64))].into());
+        let rows = list::new(vec![row.clone()]);
+        let data = Data(dict::new([("rows".to_string(), rows.clone())].into()));
+        let same = |src: &str, expected: &Value| match (evaluate(src, &data).unwrap(), expected) {
+            (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(&a, b),
+            _ => false,
+        };
+        assert!(same("data['rows']", &rows));
+        assert!(same("data['rows'][0]", &row));
+        assert!(same("data['rows'][-1]", &row));
+        assert!(same("data['rows'].first()", &row));
+        assert!(same("data['rows'].get(0, null)", &row));
+        assert!(same("[data['rows'][0]][0]", &row));
+        assert!(same("data['rows'].map(|r| r)[0]", &row));
+        assert!(same("let r = data['rows'][0] in r", &row));
+    }
+
+    #[test]
+    fn eval_compiled_folded() {
+        struct Row(i64);
+        impl VariableResolver for Row {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                (name == "n").then(|| Value::from(self.0))
+            }
+        }
+
+        let src = "n * (2 + 3) + [1, 2].length";
+        let folded = CompiledExpr::compile(src).unwrap().fold_constants();
+        assert_eq!(folded.expr(), &parser::parse_expression("n * 5 + 2").unwrap());
+        assert_eq!(folded.eval(&Row(2)).unwrap(), CompiledExpr::compile(src).unwrap().eval(&Row(2)).unwrap());
+        // a failing sub-expression is kept, and errors keep the positions they have without folding
+        for src in ["n + 10 / (5 - 5)", "(1 + 1) * missing", "[1 + 1, 'a' + 1, n]"] {
+            let plain = CompiledExpr::compile(src).unwrap().eval(&Row(2)).unwrap_err();
+            let folded = CompiledExpr::compile(src).unwrap().fold_constants().eval(&Row(2)).unwrap_err();
+            assert_eq!(folded.to_string(), plain.to_string(), "'{}'", src);
+            assert!(plain.span().is_some());
+        }
+    }
+
+    #[test]
+    fn eval_compiled() {
+        struct Row(i64);
+        impl VariableResolver for Row {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                (name == "n").then(|| Value::from(self.0))
+            }
+        }
+
+        let compiled = CompiledExpr::compile("n * n + 1").unwrap();
+        let results: Vec<Value> = (1..=3).map(|n| compiled.eval(&Row(n)).unwrap()).collect();
+        assert_eq!(results, vec![Value::from(2i64), Value::from(5i64), Value::from(10i64)]);
+        assert_eq!(compiled.expr(), &parser::parse_expression("n * n + 1").unwrap());
+        assert!(matches!(CompiledExpr::compile("n *"), Err(Error::ParseError(_))));
+        // equality ignores the layout of the source
+        let hash = |c: &CompiledExpr| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            c.hash(&mut hasher);
+            hasher.finish()
+        };
+        let spaced = CompiledExpr::compile("n*n  +  1").unwrap();
+        assert_eq!(spaced, compiled);
+        assert_eq!(hash(&spaced), hash(&compiled));
+        assert_ne!(CompiledExpr::compile("n * n + 2").unwrap(), compiled);
+        // errors are reported the same way as by `evaluate`
+        let missing = CompiledExpr::compile("m").unwrap().eval(&Row(1)).unwrap_err();
+        assert_eq!(missing.to_string(), evaluate("m", &Row(1)).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn eval_error_spans() {
+        let resolver = MockResolver::new();
+        let span = |src: &str| {
+            let err = evaluate(src, &resolver).unwrap_err();
+            let span = err.span().unwrap_or_else(|| panic!("'{}' failed without a span: {}", src, err));
+            src[span].to_string()
+        };
+
+        let err = evaluate("x + missing * 2", &resolver).unwrap_err();
+        assert_eq!(err.span(), Some(4..11));
+        assert!(matches!(err.without_span(), Error::EvaluationFailed(msg) if msg.contains("missing")));
+        assert!(err.to_string().ends_with(" at 4..11"), "{}", err);
+        // the innermost failing sub-expression is reported
+        assert_eq!(span("[1, 2].map(@ * 2).nope"), "[1, 2].map(@ * 2).nope");
+        assert_eq!(span("x + (1 / 0) * 2"), "(1 / 0)");
+        assert_eq!(span("'a'.toUpper(1) == 'A'"), "'a'.toUpper(1)");
+        assert_eq!(span("let a = 1 in a + b"), "b");
+        // inside a function body the call is reported, since the body runs on a copy of the expression
+        assert_eq!(span("1 + [1, 2].map(|n| n + missing)[0]"), "[1, 2].map(|n| n + missing)");
+        // a failure that `try` recovers from is forgotten
+        assert_eq!(span("try(missing, 0) + nope"), "nope");
+        // sub-expressions that are not evaluated, or not on their own, do not throw the location off
+        assert_eq!(span("[1, 2].map(@ * 2, nope)"), "nope");
+        assert_eq!(span("(false && nope) || math.add(1) + nope"), "math.add(1)");
+        assert_eq!(span("[x < 0 < nope, global.nope.more]"), "global.nope.more");
+        assert_eq!(CompiledExpr::compile("x +   oops").unwrap().eval(&resolver).unwrap_err().span(), Some(6..10));
+        // interpolation spans count from the start of the whole input
+        let err = evaluate_interpolations("a ${x} b ${x + nope} c", &resolver).unwrap_err();
+        assert_eq!(err.span(), Some(15..19));
+        // an evaluator given an `Expr` has no source to point at
+        assert_eq!(eval(&Evaluator::new(&resolver), "nope").unwrap_err().span(), None);
+        // an error keeps the first span it is given
+        assert_eq!(Error::DivideByZero.at(1..2).at(0..5).span(), Some(1..2));
+    }
+
+    #[test]
+    fn eval_negation() {
+        use std::cell::Cell;
+        let resolver = MockResolver::new();
+        let mut ev = Evaluator::new(&resolver);
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        ev.register("next", move |_args: &[Value]| {
+            counter.set(counter.get() + 1);
+            Ok(Value::from(counter.get()))
+        });
+
+        assert_eq!(eval(&ev, "-3 == 0 - 3").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "--3 == 3").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "-x").unwrap(), Value::from(-10i64));
+        assert_eq!(eval(&ev, "-(1 + 2)").unwrap(), Value::from(-3i64));
+        assert_eq!(eval(&ev, "-2.5").unwrap(), Value::from(-2.5));
+        assert!(matches!(eval(&ev, "-'a'"), Err(Error::TypeMismatch(_))));
+        assert!(matches!(eval(&ev, "-true"), Err(Error::TypeMismatch(_))));
+        // the operand is evaluated exactly once
+        assert_eq!(eval(&ev, "-next()").unwrap(), Value::from(-1i64));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn eval_null() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        assert_eq!(eval(&ev, "null").unwrap(), Value::Primitive(Primitive::Null));
+        assert_eq!(eval(&ev, "null == null").unwrap(), Value::from(true));
+        match eval(&ev, "null.length") {
+            Err(Error::UnknownMember { type_name, member }) => assert_eq!((type_name.as_str(), member.as_str()), ("null", "length")),
+            other => panic!("expected UnknownMember, got {:?}", other),
+        }
+        match eval(&ev, "null[0]") {
+            Err(Error::NotIndexable(what)) => assert_eq!(what, "null"),
+            other => panic!("expected NotIndexable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn eval_natural_string_order() {
+        let resolver = MockResolver::new();
+        let lexical = Evaluator::new(&resolver);
+        let natural = Evaluator::new(&resolver).natural_string_order(true);
+        let cases = [
+            ("'file2' < 'file10'", false, true),
+            ("'1.9.2' < '1.10.0'", false, true),
+            ("'v2' >= 'v10'", true, false),
+            ("'a10' <= 'a9'", true, false),
+            ("'abc' < 'abd'", true, true),
+            ("'report' < 'report1'", true, true),
+        ];
+        for (src, want_lexical, want_natural) in cases {
+            let expr = parser::parse_expression(src).unwrap();
+            assert_eq!(lexical.evaluate(&expr).unwrap(), Value::from(want_lexical), "lexical: {}", src);
+            assert_eq!(natural.evaluate(&expr).unwrap(), Value::from(want_natural), "natural: {}", src);
+        }
+        // implicit functions inherit the mode
+        let expr = parser::parse_expression("['file10', 'file2'].map(@ > 'file9')").unwrap();
+        assert_eq!(natural.evaluate(&expr).unwrap().to_string(), "[true, false]");
+    }
+
+    #[test]
+    fn eval_max_result_size() {
+        let resolver = MockResolver::new();
+        let limited = Evaluator::new(&resolver).max_result_size(100);
+
+        assert!(matches!(eval(&limited, "'a' * 10000000"), Err(Error::ResultTooLarge { size: 10000000, limit: 100 })));
+        assert_eq!(eval(&limited, "'ab' * 50").unwrap().as_str_lossy().len(), 100);
+        assert!(matches!(eval(&limited, "'ab' * 50 + '!'"), Err(Error::ResultTooLarge { .. })));
+        assert!(eval(&limited, "('x' * 60).split('')").is_ok());
+        assert!(matches!(eval(&limited, "('x' * 60).split('').flatMap([@, @])"), Err(Error::ResultTooLarge { size: 120, .. })));
+        assert!(matches!(eval(&limited, "('x,' * 50).split(',').join('--')"), Err(Error::ResultTooLarge { .. })));
+        assert!(matches!(eval(&limited, "(0..3000000000000).toList()"), Err(Error::ResultTooLarge { size: 3000000000000, limit: 100 })));
+        // unlimited by default
+        let unlimited = Evaluator::new(&resolver);
+        assert_eq!(eval(&unlimited, "('a' * 100000).length").unwrap(), Value::from(100000i64));
+        // except for turning a range into a list
+        assert!(matches!(eval(&unlimited, "(0..3000000000000).toList()"), Err(Error::ResultTooLarge { .. })));
+    }
+
+    #[test]
+    fn eval_lenient_vars() {
+        let resolver = MockResolver::new();
+        let strict = Evaluator::new(&resolver);
+        match eval(&strict, "missing") {
+            Err(Error::ResolveFailed(name)) => assert_eq!(name, "missing"),
+            other => panic!("expected ResolveFailed, got {:?}", other),
+        }
+
+        let lenient = Evaluator::new(&resolver).lenient_vars(true);
+        assert_eq!(eval(&lenient, "missing").unwrap(), Value::Primitive(Primitive::Null));
+        assert_eq!(eval(&lenient, "missing == other_missing").unwrap(), Value::from(true));
+        assert_eq!(eval(&lenient, "!missing").unwrap(), Value::from(true));
+        assert_eq!(eval(&lenient, "x").unwrap(), Value::from(10i64));
+    }
+
+    #[test]
+    fn eval_dotted_names() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        // `math` itself is unresolved, so the flattened name is used
+        assert_eq!(eval(&ev, "math.add(2, 3)").unwrap(), Value::from(5.0));
+        match eval(&ev, "math.sub(2, 3)") {
+            Err(Error::ResolveFailed(name)) => assert_eq!(name, "math.sub"),
+            other => panic!("expected ResolveFailed, got {:?}", other),
+        }
+        // a resolvable leading variable always uses member access
+        match eval(&ev, "global.b") {
+            Err(Error::ResolveFailed(name)) => assert_eq!(name, "b"),
+            other => panic!("expected ResolveFailed, got {:?}", other),
+        }
+        // every unresolved prefix is tried flattened in turn, and the whole path is what is reported missing
+        assert!(matches!(eval(&ev, "math.add.x.y"), Err(Error::UnknownMember { member, .. }) if member == "x"));
+        assert!(matches!(eval(&ev, "a.b.c"), Err(Error::ResolveFailed(name)) if name == "a.b.c"));
+        assert!(matches!(eval(&ev, "[1].nope.b"), Err(Error::UnknownMember { member, .. }) if member == "nope"));
+        let lenient = Evaluator::new(&resolver).lenient_vars(true);
+        assert_eq!(eval(&lenient, "math.sub").unwrap(), Value::Primitive(Primitive::Null));
+        assert_eq!(eval(&lenient, "a.b.c").unwrap(), Value::Primitive(Primitive::Null));
+        assert_eq!(eval(&lenient, "math.add(1, 1)").unwrap(), Value::from(2.0));
+    }
+
+    #[test]
+    fn eval_fallible_resolver() {
+        struct FlakyResolver;
+        impl VariableResolver for FlakyResolver {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                (name == "ok").then(|| Value::from(1i64))
+            }
+
+            fn try_resolve(&self, name: &str) -> Result<Option<Value>> {
+                if name.starts_with("down") {
+                    return Err(Error::EvaluationFailed("backend down".into()));
+                }
+                Ok(self.resolve(name))
+            }
+        }
+
+        let resolver = FlakyResolver;
+        for ev in [Evaluator::new(&resolver), Evaluator::new(&resolver).lenient_vars(true)] {
+            assert_eq!(eval(&ev, "ok + 1").unwrap(), Value::from(2i64));
+            // a failed lookup is reported as is, not as a missing variable, and lenient mode does not hide it
+            for src in ["down", "down.x", "[1].map(@ + down)"] {
+                match eval(&ev, src) {
+                    Err(Error::EvaluationFailed(msg)) => assert_eq!(msg, "backend down"),
+                    other => panic!("expected the backend error for {}, got {:?}", src, other),
+                }
+            }
+        }
+        assert!(matches!(eval(&Evaluator::new(&resolver), "missing"), Err(Error::ResolveFailed(_))));
+    }
+
+    #[test]
+    fn eval_integer_overflow() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        assert_eq!(eval(&ev, "4611686018427387903 * 2 + 1").unwrap(), Value::from(i64::MAX));
+        assert_eq!(eval(&ev, "-9223372036854775807 - 1").unwrap(), Value::from(i64::MIN));
+        assert_eq!(eval(&ev, "-3037000499 * 3037000499").unwrap(), Value::from(-9_223_372_030_926_249_001i64));
+        for (src, message) in [
+            ("9223372036854775807 * 2", "9223372036854775807 * 2"),
+            ("4611686018427387904 * -3", "4611686018427387904 * -3"),
+            ("3037000500 * 3037000500", "3037000500 * 3037000500"),
+            ("9223372036854775807 + 1", "9223372036854775807 + 1"),
+            ("-9223372036854775807 - 2", "-9223372036854775807 - 2"),
+            ("(-9223372036854775807 - 1) % -1", "-9223372036854775808 % -1"),
+            ("-(-9223372036854775807 - 1)", "-(-9223372036854775808)"),
+            ("'ab' * 9223372036854775807", "string of 2 bytes * 9223372036854775807"),
+            ("'ab' * 4611686018427387904", "string of 2 bytes * 4611686018427387904"),
+        ] {
+            match eval(&ev, src) {
+                Err(Error::IntegerOverflow(m)) => assert_eq!(m, message),
+                other => panic!("expected IntegerOverflow for {}, got {:?}", src, other),
+            }
+        }
+        // floats never overflow to an error
+        assert_eq!(eval(&ev, "9223372036854775807.0 * 2").unwrap(), Value::from(i64::MAX as f64 * 2.0));
+    }
+
+    #[test]
+    fn eval_bitwise() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        assert_eq!(eval(&ev, "6 & 3 == 2").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "1 << 4 == 16").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "6 | 3").unwrap(), Value::from(7i64));
+        assert_eq!(eval(&ev, "6 ~ 3").unwrap(), Value::from(5i64));
+        assert_eq!(eval(&ev, "-16 >> 2").unwrap(), Value::from(-4i64));
+        assert_eq!(eval(&ev, "1 << 63").unwrap(), Value::from(i64::MIN));
+        assert_eq!(eval(&ev, "x & 0xF0 | 1").unwrap(), Value::from(1i64));
+        for src in ["1 << 64", "1 >> -1", "1 << -1"] {
+            assert!(matches!(eval(&ev, src), Err(Error::EvaluationFailed(_))), "expected an error for {}", src);
+        }
+        for src in ["1.0 & 1", "1 | true", "\"a\" << 1"] {
+            assert!(matches!(eval(&ev, src), Err(Error::TypeMismatch(_))), "expected a type mismatch for {}", src);
+        }
+    }
+
+    #[test]
+    fn eval_depth_limit() {
+        // unoptimized builds need more stack per level than a test thread has for the default limit
+        let deep = std::thread::Builder::new().stack_size(16 << 20).spawn(|| {
+            let resolver = MockResolver::new();
+            let ev = Evaluator::new(&resolver);
+            assert_eq!(eval(&ev, &format!("1{}", " + 1".repeat(200))).unwrap(), Value::from(201i64));
+            assert!(matches!(eval(&ev, &format!("1{}", " + 1".repeat(400))), Err(Error::RecursionLimit(DEFAULT_MAX_DEPTH))));
+            // the depth is restored after a failure, so the evaluator stays usable
+            assert_eq!(eval(&ev, "x + 1").unwrap(), Value::from(11i64));
+        });
+        deep.unwrap().join().unwrap();
+
+        let resolver = MockResolver::new();
+        let shallow = Evaluator::new(&resolver).max_depth(3);
+        assert_eq!(eval(&shallow, "1 + 2").unwrap(), Value::from(3i64));
+        assert!(matches!(eval(&shallow, "1 + 2 + 3 + 4"), Err(Error::RecursionLimit(3))));
+        // the body of an `@` function counts towards the limit of the expression it appears in
+        assert!(matches!(eval(&shallow, "[1].map(@ + 1 + 1)"), Err(Error::RecursionLimit(3))));
+    }
+
+    #[test]
+    fn eval_division_by_zero_modes() {
+        let resolver = MockResolver::new();
+
+        let strict = Evaluator::new(&resolver);
+        for src in ["1 / 0", "1.0 / 0", "1 / 0.0", "0.0 / 0.0", "1.5 % 0.0", "5 % 0"] {
+            assert!(matches!(eval(&strict, src), Err(Error::DivideByZero)), "expected DivideByZero for {}", src);
+        }
+
+        let ieee = Evaluator::new(&resolver).ieee_division(true);
+        assert_eq!(eval(&ieee, "1.0 / 0").unwrap(), Value::from(f64::INFINITY));
+        assert_eq!(eval(&ieee, "-1 / 0.0").unwrap(), Value::from(f64::NEG_INFINITY));
+        assert!(matches!(eval(&ieee, "0.0 / 0.0").unwrap(), Value::Primitive(Primitive::Float(f)) if f.is_nan()));
+        assert!(matches!(eval(&ieee, "1.5 % 0.0").unwrap(), Value::Primitive(Primitive::Float(f)) if f.is_nan()));
+        // NaN is not equal to anything, including itself
+        assert_eq!(eval(&ieee, "0.0 / 0.0 == 0.0 / 0.0").unwrap(), Value::from(false));
+        assert_eq!(eval(&ieee, "0.0 / 0.0 != 0.0 / 0.0").unwrap(), Value::from(true));
+        assert_eq!(eval(&ieee, "0.0 / 0.0 < 1").unwrap(), Value::from(false));
+        // integer division by zero still fails
+        assert!(matches!(eval(&ieee, "1 / 0"), Err(Error::DivideByZero)));
+        assert!(matches!(eval(&ieee, "5 % 0"), Err(Error::DivideByZero)));
+        // bad results can be detected with the number members
+        assert_eq!(eval(&ieee, "(0.0 / 0.0).isNaN").unwrap(), Value::from(true));
+        assert_eq!(eval(&ieee, "(1.0 / 0.0).isNaN").unwrap(), Value::from(false));
+        assert_eq!(eval(&ieee, "(-1.0 / 0.0).isInfinite").unwrap(), Value::from(true));
+        assert_eq!(eval(&ieee, "(1.0 / 4).isInfinite").unwrap(), Value::from(false));
+    }
+
+    #[test]
+    fn eval_type_predicates() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        let predicates = ["isNumber", "isString", "isBool", "isList", "isDict", "isNull"];
+        // each value and the one predicate it satisfies, if any
+        for (value, matching) in [
+            ("1", Some("isNumber")),
+            ("2.5", Some("isNumber")),
+            ("'s'", Some("isString")),
+            ("true", Some("isBool")),
+            ("[1]", Some("isList")),
+            ("{}", Some("isDict")),
+            ("null", Some("isNull")),
+            ("add", None),
+            ("global", None),
+        ] {
+            for predicate in predicates {
+                let src = format!("{}({})", predicate, value);
+                assert_eq!(eval(&ev, &src).unwrap(), Value::from(matching == Some(predicate)), "{}", src);
+            }
+        }
+        assert_eq!(eval(&ev, "type(global)").unwrap(), Value::from("global"));
+        assert_eq!(eval(&ev, "[1, 'a', null].map(isNumber(@))").unwrap(), list::new(vec![Value::from(true), Value::from(false), Value::from(false)]));
+        assert!(eval(&ev, "isNull()").is_err());
+    }
+
+    #[test]
+    fn eval_math_builtins() {
+        let resolver = MockResolver::new();
+        let plain = Evaluator::new(&resolver);
+        assert!(matches!(eval(&plain, "sqrt(4)"), Err(Error::ResolveFailed(_))));
+
+        let mut ev = Evaluator::new(&resolver);
+        builtins::install_math(&mut ev);
+        for (src, expected) in [
+            ("abs(-3)", Value::from(3i64)),
+            ("abs(-2.5)", Value::from(2.5)),
+            ("floor(2.7)", Value::from(2i64)),
+            ("floor(-2.5)", Value::from(-3i64)),
+            ("ceil(2.1)", Value::from(3i64)),
+            ("ceil(4)", Value::from(4i64)),
+            ("round(2.5)", Value::from(3i64)),
+            ("round(-1.4)", Value::from(-1i64)),
+            ("round(1e300)", Value::from(1e300)),
+            ("sqrt(16)", Value::from(4.0)),
+            ("sqrt(2.25)", Value::from(1.5)),
+            ("pow(2, 10)", Value::from(1024.0)),
+            ("pow(4, 0.5)", Value::from(2.0)),
+            ("min(3, 1.5, 2)", Value::from(1.5)),
+            ("max(3, 1.5, 2)", Value::from(3i64)),
+            ("max(x)", Value::from(10i64)),
+            ("[1.2, 3.8].map(round(@))", list::new(vec![Value::from(1i64), Value::from(4i64)])),
+        ] {
+            assert_eq!(eval(&ev, src).unwrap(), expected, "{}", src);
+        }
+
+        // arity and argument errors
+        for src in ["abs()", "abs(1, 2)", "pow(2)", "min()", "sqrt(-1)"] {
+            assert!(matches!(eval(&ev, src), Err(Error::EvaluationFailed(_))), "expected an error for {}", src);
+        }
+        for src in ["abs('a')", "floor(true)", "max(1, '2')", "pow(null, 1)"] {
+            assert!(matches!(eval(&ev, src), Err(Error::TypeMismatch(_))), "expected a type mismatch for {}", src);
+        }
+        assert!(matches!(eval(&ev, "abs(-9223372036854775807 - 1)"), Err(Error::IntegerOverflow(_))));
+    }
+
+    #[test]
+    fn eval_random_with_seed() {
+        let resolver = MockResolver::new();
+        let src = "[random(), random(), randomInt(1, 6), randomInt(-3, 3)]";
+
+        let a = Evaluator::new(&resolver).rng_seed(42);
+        let b = Evaluator::new(&resolver).rng_seed(42);
+        let first = eval(&a, src).unwrap();
+        assert_eq!(first, eval(&b, src).unwrap());
+        assert_ne!(first, eval(&a, src).unwrap());
+
+        for _ in 0..200 {
+            let f = eval(&a, "random()").unwrap().to_float_lossy().unwrap();
+            assert!((0.0..1.0).contains(&f));
+            assert!(matches!(eval(&a, "randomInt(1, 6)").unwrap(), Value::Primitive(Primitive::Int(1..=6))));
+        }
+        assert_eq!(eval(&a, "randomInt(7, 7)").unwrap(), Value::from(7i64));
+        assert!(eval(&a, "randomInt(2, 1)").is_err());
+        assert!(eval(&a, "randomInt(1.5, 2)").is_err());
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn eval_uuid() {
+        let resolver = MockResolver::new();
+        let uuid = |ev: &Evaluator<MockResolver>| eval(ev, "uuid()").unwrap().to_string();
+
+        let ev = Evaluator::new(&resolver).rng_seed(7);
+        let id = uuid(&ev);
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert_ne!(id, uuid(&ev));
+        assert_eq!(id, uuid(&Evaluator::new(&resolver).rng_seed(7)));
+    }
+
+    #[test]
+    fn eval_try() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        // NoSuchKey is caught
+        assert_eq!(eval(&ev, "try({'a': 1}['b'], 0)").unwrap(), Value::from(0i64));
+        // DivideByZero is caught
+        assert_eq!(eval(&ev, "try(x / 0, -1)").unwrap(), Value::from(-1i64));
+        // the default is not evaluated when the expression succeeds
+        assert_eq!(eval(&ev, "try(x, 1 / 0)").unwrap(), Value::from(10i64));
+        // errors in the default are not caught
+        match eval(&ev, "try(missing, 1 / 0)") {
+            Err(Error::DivideByZero) => (),
+            other => panic!("expected DivideByZero, got {:?}", other),
+        }
+        match eval(&ev, "try(1)") {
+            Err(Error::EvaluationFailed(msg)) => assert_eq!(msg, "try expects 2 args"),
+            other => panic!("expected EvaluationFailed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn eval_typed() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        assert_eq!(ev.evaluate_typed::<i64>(&parser::parse_expression("x * 2").unwrap()).unwrap(), 20);
+        assert_eq!(ev.evaluate_typed::<f64>(&parser::parse_expression("1.5 + 1").unwrap()).unwrap(), 2.5);
+        assert!(ev.evaluate_typed::<bool>(&parser::parse_expression("truth && x > 1").unwrap()).unwrap());
+        assert_eq!(ev.evaluate_typed::<String>(&parser::parse_expression("'ab'.toUpper()").unwrap()).unwrap(), "AB");
+        match ev.evaluate_typed::<i64>(&parser::parse_expression("'ab'").unwrap()) {
+            Err(Error::TypeMismatch(msg)) => assert_eq!(msg, "expected int"),
+            other => panic!("expected TypeMismatch, got {:?}", other),
+        }
+        match ev.evaluate_typed::<String>(&parser::parse_expression("[1]").unwrap()) {
+            Err(Error::TypeMismatch(msg)) => assert_eq!(msg, "expected string"),
+            other => panic!("expected TypeMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn eval_lists_and_indexing() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        // [10, 20, 30][1] => 20
+        assert_eq!(eval(&ev, "[10, 20, 30][1]").unwrap(), Value::from(20i64));
+        // [10][1] => IndexOutOfBounds
+        match eval(&ev, "[10][1]") {
+            Err(Error::IndexOutOfBounds { index, len }) => {
+                assert_eq!(index, 1);
+                assert_eq!(len, 1);
+            }
+            other => panic!("expected IndexOutOfBounds, got {:?}", other),
+        }
         // [10]["0"] => WrongIndexType
-        match ev.evaluate(&parser::parse_expression("[10][\"0\"]").unwrap()) {
+        match eval(&ev, "[10][\"0\"]") {
             Err(Error::NotIndexable(idx)) => assert_eq!(idx, "0"),
             other => panic!("expected NotIndexable(0), got {:?}", other),
         }
         // negative indices
-        assert_eq!(ev.evaluate(&parser::parse_expression("[10, 20, 30][-1]").unwrap()).unwrap(), Value::from(30i64));
-        assert_eq!(ev.evaluate(&parser::parse_expression("[10, 20, 30][-3]").unwrap()).unwrap(), Value::from(10i64));
-        match ev.evaluate(&parser::parse_expression("[10, 20, 30][-4]").unwrap()) {
+        assert_eq!(eval(&ev, "[10, 20, 30][-1]").unwrap(), Value::from(30i64));
+        assert_eq!(eval(&ev, "[10, 20, 30][-3]").unwrap(), Value::from(10i64));
+        match eval(&ev, "[10, 20, 30][-4]") {
+            Err(Error::IndexOutOfBounds { index, len }) => {
+                assert_eq!(index, -4);
+                assert_eq!(len, 3);
+            }
+            other => panic!("expected IndexOutOfBounds, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn eval_string_indexing() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        match eval(&ev, "'abc'[3]") {
+            Err(Error::IndexOutOfBounds { index, len }) => {
+                assert_eq!(index, 3);
+                assert_eq!(len, 3);
+            }
+            other => panic!("expected IndexOutOfBounds, got {:?}", other),
+        }
+        match eval(&ev, "'abc'[-4]") {
             Err(Error::IndexOutOfBounds { index, len }) => {
                 assert_eq!(index, -4);
                 assert_eq!(len, 3);
             }
             other => panic!("expected IndexOutOfBounds, got {:?}", other),
         }
+        match eval(&ev, "'abc'['a']") {
+            Err(Error::NotIndexable(idx)) => assert_eq!(idx, "a"),
+            other => panic!("expected NotIndexable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn eval_index_non_indexable() {
+        let resolver = MockResolver::new();
+        let ev = Evaluator::new(&resolver);
+        for (src, type_name) in [("add[0]", "function"), ("add['k']", "function"), ("true[0]", "bool"), ("1.5[0]", "number"), ("x[0]", "number")] {
+            match eval(&ev, src) {
+                Err(Error::NotIndexable(t)) => assert_eq!(t, type_name, "for {}", src),
+                other => panic!("expected NotIndexable for {}, got {:?}", src, other),
+            }
+        }
     }
 
     #[test]
@@ -440,47 +2305,57 @@ mod tests {
         let resolver = MockResolver::new();
         let ev = Evaluator::new(&resolver);
         // Dict via [key]
-        assert_eq!(ev.evaluate(&parser::parse_expression("{\"a\": 1, \"b\": 2}[\"b\"]").unwrap()).unwrap(), Value::from(2i64));
-        match ev.evaluate(&parser::parse_expression("{\"a\": 1}[\"z\"]").unwrap()) {
+        assert_eq!(eval(&ev, "{\"a\": 1, \"b\": 2}[\"b\"]").unwrap(), Value::from(2i64));
+        match eval(&ev, "{\"a\": 1}[\"z\"]") {
             Err(Error::NoSuchKey(k)) => assert_eq!(k, "z"),
             other => panic!("expected NoSuchKey, got {:?}", other),
         }
-        match ev.evaluate(&parser::parse_expression("{\"a\": 1}[0]").unwrap()) {
+        match eval(&ev, "{\"a\": 1}[0]") {
             Err(Error::NotIndexable(idx)) => assert_eq!(idx, "0"),
             other => panic!("expected NotIndexable(0), got {:?}", other),
         }
         // Members: properties and methods
         // string.length property
-        assert_eq!(ev.evaluate(&parser::parse_expression("'abc'.length").unwrap()).unwrap(), Value::from(3i64));
+        assert_eq!(eval(&ev, "'abc'.length").unwrap(), Value::from(3i64));
         // string methods
-        assert_eq!(ev.evaluate(&parser::parse_expression("'ab'.toUpper()").unwrap()).unwrap().to_string(), "AB");
-        assert_eq!(ev.evaluate(&parser::parse_expression("' Ab '.trim().length").unwrap()).unwrap(), Value::from(2i64));
+        assert_eq!(eval(&ev, "'ab'.toUpper()").unwrap().to_string(), "AB");
+        assert_eq!(eval(&ev, "' Ab '.trim().length").unwrap(), Value::from(2i64));
         // list.length property
-        assert_eq!(ev.evaluate(&parser::parse_expression("[1,2,3].length").unwrap()).unwrap(), Value::from(3i64));
+        assert_eq!(eval(&ev, "[1,2,3].length").unwrap(), Value::from(3i64));
         // dict.length property and keys()/values()
-        assert_eq!(ev.evaluate(&parser::parse_expression("{\"a\":1, \"b\":2}.length").unwrap()).unwrap(), Value::from(2i64));
-        assert_eq!(ev.evaluate(&parser::parse_expression("{\"a\":1}.keys().length").unwrap()).unwrap(), Value::from(1i64));
+        assert_eq!(eval(&ev, "{\"a\":1, \"b\":2}.length").unwrap(), Value::from(2i64));
+        assert_eq!(eval(&ev, "{\"a\":1}.keys().length").unwrap(), Value::from(1i64));
+        // methods are values until called; only properties evaluate directly
+        assert_eq!(eval(&ev, "{\"a\":1}.keys").unwrap().type_name(), "function");
         // errors: dict dot key is unknown member now
-        match ev.evaluate(&parser::parse_expression("{\"a\": 1}.a").unwrap()) {
+        match eval(&ev, "{\"a\": 1}.a") {
             Err(Error::UnknownMember { member, .. }) => assert_eq!(member, "a"),
             other => panic!("expected UnknownMember, got {:?}", other),
         }
         // errors: unknown member on list
-        match ev.evaluate(&parser::parse_expression("[1].toUpper").unwrap()) {
+        match eval(&ev, "[1].toUpper") {
             Err(Error::UnknownMember { member, .. }) => assert_eq!(member, "toUpper"),
             other => panic!("expected UnknownMember, got {:?}", other),
         }
+        // errors: unknown member on string
+        match eval(&ev, "'abc'.nope") {
+            Err(Error::UnknownMember { type_name, member }) => {
+                assert_eq!(type_name, "string");
+                assert_eq!(member, "nope");
+            }
+            other => panic!("expected UnknownMember, got {:?}", other),
+        }
         // calling non-call property is NotCallable
-        match ev.evaluate(&parser::parse_expression("'abc'.length()").unwrap()) {
+        match eval(&ev, "'abc'.length()") {
             Err(Error::NotCallable) => (),
             other => panic!("expected NotCallable, got {:?}", other),
         }
         // Nested
-        assert_eq!(ev.evaluate(&parser::parse_expression("{\"xs\": [10, 20]}[\"xs\"][1]").unwrap()).unwrap(), Value::from(20i64));
+        assert_eq!(eval(&ev, "{\"xs\": [10, 20]}[\"xs\"][1]").unwrap(), Value::from(20i64));
 
         // Computed dict key in literal and runtime enforcement of key type
-        assert_eq!(ev.evaluate(&parser::parse_expression("{\"a\" + \"b\": 1}[\"ab\"]").unwrap()).unwrap(), Value::from(1i64));
-        match ev.evaluate(&parser::parse_expression("{1: 2}").unwrap()) {
+        assert_eq!(eval(&ev, "{\"a\" + \"b\": 1}[\"ab\"]").unwrap(), Value::from(1i64));
+        match eval(&ev, "{1: 2}") {
             Err(Error::TypeMismatch(msg)) => assert_eq!(msg, "dict key must be a string"),
             other => panic!("expected TypeMismatch for dict key, got {:?}", other),
         }
@@ -490,12 +2365,12 @@ mod tests {
     fn eval_truthiness_lists_dicts() {
         let resolver = MockResolver::new();
         let ev = Evaluator::new(&resolver);
-        assert_eq!(ev.evaluate(&parser::parse_expression("![]").unwrap()).unwrap(), Value::from(true));
-        assert_eq!(ev.evaluate(&parser::parse_expression("!![]").unwrap()).unwrap(), Value::from(false));
-        assert_eq!(ev.evaluate(&parser::parse_expression("![1]").unwrap()).unwrap(), Value::from(false));
-        assert_eq!(ev.evaluate(&parser::parse_expression("!![1]").unwrap()).unwrap(), Value::from(true));
-        assert_eq!(ev.evaluate(&parser::parse_expression("!{}").unwrap()).unwrap(), Value::from(true));
-        assert_eq!(ev.evaluate(&parser::parse_expression("!!{\"a\":1}").unwrap()).unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "![]").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "!![]").unwrap(), Value::from(false));
+        assert_eq!(eval(&ev, "![1]").unwrap(), Value::from(false));
+        assert_eq!(eval(&ev, "!![1]").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "!{}").unwrap(), Value::from(true));
+        assert_eq!(eval(&ev, "!!{\"a\":1}").unwrap(), Value::from(true));
     }
 
     #[test]
@@ -510,4 +2385,96 @@ mod tests {
         assert_eq!(evaluate_interpolations("x${{'foo': 'bar', 'baz': 'bam'}['foo']}y", &resolver).unwrap(), "xbary");
         assert_eq!(evaluate_interpolations("x${{\"foo\": \"bar\", \"baz\": \"bam\"}[\"foo\"]}y", &resolver).unwrap(), "xbary");
     }
+
+    #[test]
+    fn eval_interpolation_escapes() {
+        let resolver = MockResolver::new();
+        let interpolate = |src: &str| evaluate_interpolations(src, &resolver).unwrap();
+        assert_eq!(interpolate("price: \\${x}"), "price: ${x}");
+        assert_eq!(interpolate("\\${x}${x}"), "${x}10");
+        assert_eq!(interpolate("\\${x} = ${x}, \\${"), "${x} = 10, ${");
+        // an escaped backslash before a real interpolation
+        assert_eq!(interpolate("C:\\\\${x}"), "C:\\10");
+        // other backslashes and dollars are plain text
+        assert_eq!(interpolate("C:\\dir $5 $x \\$ ${x}"), "C:\\dir $5 $x \\$ 10");
+        // a string literal inside an interpolation is interpolated too, so it escapes `${` the same way
+        assert_eq!(interpolate("${'a\\\\${b}'}"), "a${b}");
+    }
+
+    #[test]
+    fn eval_nested_interpolation() {
+        let resolver = MockResolver::new();
+        let interpolate = |src: &str| evaluate_interpolations(src, &resolver);
+        assert_eq!(interpolate("${ 'a' + '${x}' }").unwrap(), "a10");
+        assert_eq!(interpolate("${ '[${x * 2}]'.length } ${ \"${x}\" == '10' }").unwrap(), "4 true");
+        assert_eq!(interpolate("${ '${missing:-none}' }").unwrap(), "none");
+        assert_eq!(interpolate("${ [1, 2].map(|n| '#${n}') }").unwrap(), "[#1, #2]");
+        // literals without `${` are left alone
+        assert_eq!(interpolate("${ 'a{b}$c' + '}' }").unwrap(), "a{b}$c}");
+        // a failure inside is located at the literal
+        let err = interpolate("ab ${ 1 + '${missing}'.length }").unwrap_err();
+        assert_eq!(err.span(), Some(10..22));
+        // plain evaluation does not interpolate
+        let ev = Evaluator::new(&resolver);
+        assert_eq!(eval(&ev, "'a${x}'").unwrap().to_string(), "a${x}");
+    }
+
+    #[test]
+    fn eval_interpolation_to_sink() {
+        let resolver = MockResolver::new();
+        let mut out = String::from("> ");
+        evaluate_interpolations_to("x=${x}, y=${[1, 2][1]}.", &resolver, &mut out).unwrap();
+        assert_eq!(out, "> x=10, y=2.");
+
+        // output before the failing segment has already been written
+        let mut partial = String::new();
+        assert!(evaluate_interpolations_to("a${x}b${missing}c", &resolver, &mut partial).is_err());
+        assert_eq!(partial, "a10b");
+
+        struct Full;
+        impl fmt::Write for Full {
+            fn write_str(&mut self, _s: &str) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+        assert!(evaluate_interpolations_to("text", &resolver, &mut Full).is_err());
+
+        // an evaluator's own functions and options apply
+        let mut ev = Evaluator::new(&resolver).lenient_vars(true);
+        ev.register("twice", |args: &[Value]| Ok(Value::from(args[0].as_str_lossy().repeat(2))));
+        let mut out = String::new();
+        ev.evaluate_interpolated_to("${twice('ab')}, ${missing}, ${'${twice(x)}'}", &mut out).unwrap();
+        assert_eq!(out, "abab, null, 1010");
+        assert!(evaluate_interpolations_to("${twice('ab')}", &resolver, &mut String::new()).is_err());
+    }
+
+    #[test]
+    fn eval_interpolation_default() {
+        struct Vars;
+        impl VariableResolver for Vars {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                match name {
+                    "name" => Some(Value::from("bob")),
+                    "empty" => Some(Value::from("")),
+                    "nothing" => Some(Value::Primitive(Primitive::Null)),
+                    "zero" => Some(Value::from(0i64)),
+                    "app.port" => Some(Value::from(80i64)),
+                    _ => None,
+                }
+            }
+        }
+        let eval = |src: &str| evaluate_interpolations(src, &Vars).unwrap();
+        assert_eq!(eval("hi ${name:-stranger}!"), "hi bob!");
+        assert_eq!(eval("hi ${missing:-stranger}!"), "hi stranger!");
+        assert_eq!(eval("hi ${ empty :-a b c}!"), "hi a b c!");
+        assert_eq!(eval("${nothing:-none}"), "none");
+        assert_eq!(eval("${zero:-none}"), "0");
+        assert_eq!(eval("${missing:-}x"), "x");
+        assert_eq!(eval("port ${app.port:-8080}, host ${app.host:-localhost}"), "port 80, host localhost");
+        assert_eq!(eval("${missing.deep:-d}"), "d");
+        // anything that is not `name:-` is an ordinary expression
+        assert_eq!(eval("${[1, 2].get(5, 'x:-y')}"), "x:-y");
+        assert!(evaluate_interpolations("${missing:-oops", &Vars).is_err());
+        assert!(evaluate_interpolations("${name.nope:-x}", &Vars).is_err());
+    }
 }
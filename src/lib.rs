@@ -1,3 +1,6 @@
+pub mod builtins;
 pub mod evaluator;
 pub mod parser;
+pub mod resolvers;
 pub mod types;
+pub mod validator;
@@ -1,19 +1,36 @@
 use crate::types::error::{Error, Result};
-use crate::types::expression::{BinaryOp, Expr, UnaryOp};
+use crate::types::expression::{Associativity, BinaryOp, Expr, UnaryOp};
 use crate::types::primitive::Primitive;
+use crate::types::token::{Token, TokenKind};
 use pest::Parser;
 use pest::iterators::Pair;
 use pest::pratt_parser::{Assoc, Op, PrattParser};
+use std::ops::Range;
 
 #[derive(pest_derive::Parser)]
 #[grammar = "expr.pest"]
 struct InnerParser;
 
+/// Parses a complete expression. The whole input must be consumed: trailing tokens such as `1 2` or `1 + 2 )`
+/// are a `ParseError` rather than silently parsing a prefix.
 pub fn parse_expression(input: &str) -> Result<Expr> {
-    parse_internal(input, Rule::program).map(|r| r.0)
+    parse_expression_with_max_nesting(input, DEFAULT_MAX_NESTING)
 }
 
-pub(crate) fn parse_internal(input: &str, rule: Rule) -> Result<(Expr, usize)> {
+/// Like `parse_expression`, but allowing `max_nesting` levels of nesting instead of `DEFAULT_MAX_NESTING`. A
+/// larger limit may need a larger thread stack, as may evaluating the result (see `Evaluator::max_depth`).
+pub fn parse_expression_with_max_nesting(input: &str, max_nesting: usize) -> Result<Expr> {
+    parse_internal(input, Rule::program, max_nesting).map(|r| r.0.0)
+}
+
+/// Like `parse_expression`, but also returns the byte range in `input` of every node of the expression, in the
+/// order a `Visitor` reaches them: a node before its children, and children in the order `walk_expr` visits them.
+pub(crate) fn parse_spanned(input: &str) -> Result<Spanned> {
+    parse_internal(input, Rule::program, DEFAULT_MAX_NESTING).map(|r| r.0)
+}
+
+pub(crate) fn parse_internal(input: &str, rule: Rule, max_nesting: usize) -> Result<(Spanned, usize)> {
+    check_nesting(input, max_nesting)?;
     let mut pairs = InnerParser::parse(rule, input).map_err(|e| Error::ParseError(format!("parse error: {}", e)))?;
     let pair = pairs.next().expect("program always produces one pair");
 
@@ -24,94 +41,276 @@ pub(crate) fn parse_internal(input: &str, rule: Rule) -> Result<(Expr, usize)> {
     Ok((expr, end_pos))
 }
 
+/// How deeply `parse_expression` lets an expression nest: levels of brackets, lambdas and `let`s, where every
+/// operator or postfix access (`.name`, `[i]`, `(args)`) counts as an eighth of a level. Parsing and walking an
+/// expression recurse once per level, so deeper input is a `ParseError` instead of a stack overflow.
+pub const DEFAULT_MAX_NESTING: usize = 64;
+
+// Nesting is measured in eighths of a level
+const LEVEL: usize = 8;
+
+// Rejects input that nests deeper than `max_nesting` before the recursive parser sees it, going through the
+// grammar's tokens one at a time, so any depth is safe to check. A `}` at the top level ends the scan, as it ends
+// an interpolated expression.
+fn check_nesting(input: &str, max_nesting: usize) -> Result<()> {
+    let mut tokens = Tokens { input, pos: 0 };
+    // the nesting of each open bracket's current element, which a `,` or `:` starts afresh
+    let mut frames = vec![0usize];
+    let mut depth = 0;
+    let mut operand_expected = true;
+    while let Some((rule, span)) = tokens.next() {
+        let text = &input[span.clone()];
+        let mut cost = 0;
+        match (rule, text) {
+            (Rule::punctuation, "(" | "[" | "{") => {
+                // after an operand, a call or an index is also a postfix access, part of the enclosing element
+                let access = usize::from(!operand_expected);
+                *frames.last_mut().expect("the top level frame is never popped") += access;
+                frames.push(0);
+                depth += LEVEL + access;
+                operand_expected = true;
+            }
+            (Rule::punctuation, ")" | "]" | "}") => {
+                if frames.len() == 1 {
+                    break;
+                }
+                depth -= LEVEL + frames.pop().unwrap_or(0);
+                operand_expected = false;
+            }
+            (Rule::punctuation, "," | ":") => {
+                let frame = frames.last_mut().expect("the top level frame is never popped");
+                depth -= *frame;
+                *frame = 0;
+                operand_expected = true;
+            }
+            (Rule::operator, "|" | "||") if operand_expected => {
+                // a lambda: its parameter list runs to the next `|`
+                if text == "|" {
+                    while tokens.next().is_some_and(|(_, span)| !input[span].contains('|')) {}
+                }
+                cost = LEVEL;
+            }
+            (Rule::let_kw, _) => cost = LEVEL,
+            (Rule::operator | Rule::punctuation, _) => {
+                cost = 1;
+                operand_expected = true;
+            }
+            (Rule::unknown, _) => {}
+            _ => operand_expected = false,
+        }
+        *frames.last_mut().expect("the top level frame is never popped") += cost;
+        depth += cost;
+        if depth > max_nesting * LEVEL {
+            return Err(Error::ParseError(format!("expression nested too deeply at byte {} (limit: {} levels)", span.start, max_nesting)));
+        }
+    }
+    Ok(())
+}
+
+// The tokens of `tokenize`, lexed one at a time so that a scan which stops early does not lex the rest
+struct Tokens<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = (Rule, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pair = InnerParser::parse(Rule::next_token, &self.input[self.pos..]).ok()?.next()?.into_inner().next()?;
+        let span = self.pos + pair.as_span().start()..self.pos + pair.as_span().end();
+        self.pos = span.end;
+        Some((pair.as_rule(), span))
+    }
+}
+
+pub(crate) type Spanned = (Expr, Vec<Range<usize>>);
+
+// `expr` spanning `span`, followed by the spans of its children's subtrees in visiting order
+fn node(expr: Expr, span: Range<usize>, children: impl IntoIterator<Item = Vec<Range<usize>>>) -> Spanned {
+    let mut spans = vec![span];
+    for child in children {
+        spans.extend(child);
+    }
+    (expr, spans)
+}
+
+fn span_of(pair: &Pair<Rule>) -> Range<usize> {
+    pair.as_span().start()..pair.as_span().end()
+}
+
+/// Splits `input` into tokens without parsing it, for syntax highlighting and bracket matching. Unlike
+/// `parse_expression` this accepts incomplete or invalid expressions; anything unrecognized becomes an
+/// `Unknown` token. Whitespace and comments are skipped.
+pub fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let pairs = InnerParser::parse(Rule::tokens, input).map_err(|e| Error::ParseError(format!("parse error: {}", e)))?;
+    let root = pairs.into_iter().next().expect("tokens always produces one pair");
+    let mut tokens = Vec::new();
+    for pair in root.into_inner() {
+        let kind = match pair.as_rule() {
+            Rule::number => TokenKind::Number,
+            Rule::string => TokenKind::String,
+            Rule::boolean | Rule::null_kw | Rule::let_kw => TokenKind::Keyword,
+            Rule::ident | Rule::current => TokenKind::Ident,
+            Rule::operator => TokenKind::Operator,
+            Rule::punctuation => TokenKind::Punctuation,
+            Rule::unterminated_string | Rule::unknown => TokenKind::Unknown,
+            Rule::EOI => continue,
+            r => return Err(Error::InternalParserError(format!("unexpected token: {:?}", r))),
+        };
+        let span = pair.as_span();
+        tokens.push(Token { kind, start: span.start(), end: span.end() });
+    }
+    Ok(tokens)
+}
+
+thread_local! {
+    // building the PrattParser is not free, so build it once per thread and reuse it for every parse
+    static PRATT: PrattParser<Rule> = pratt();
+}
+
+// Operator levels come from `BinaryOp::precedence`/`associativity`; each rule is represented by one of the
+// operators it produces (all operators of a rule share a level).
 fn pratt() -> PrattParser<Rule> {
-    PrattParser::new()
-        .op(Op::infix(Rule::op_or, Assoc::Left))
-        .op(Op::infix(Rule::op_and, Assoc::Left))
-        .op(Op::infix(Rule::op_eq, Assoc::Left))
-        .op(Op::infix(Rule::op_cmp, Assoc::Left))
-        .op(Op::infix(Rule::op_add, Assoc::Left))
-        .op(Op::infix(Rule::op_mul, Assoc::Left))
-        .op(Op::infix(Rule::op_pow, Assoc::Right))
+    let mut levels = [
+        (Rule::op_or, BinaryOp::Or),
+        (Rule::op_and, BinaryOp::And),
+        (Rule::op_eq, BinaryOp::Eq),
+        (Rule::op_cmp, BinaryOp::Lt),
+        (Rule::op_range, BinaryOp::Range),
+        (Rule::op_bitor, BinaryOp::BitOr),
+        (Rule::op_bitxor, BinaryOp::BitXor),
+        (Rule::op_bitand, BinaryOp::BitAnd),
+        (Rule::op_shift, BinaryOp::Shl),
+        (Rule::op_add, BinaryOp::Add),
+        (Rule::op_mul, BinaryOp::Mul),
+        (Rule::op_pow, BinaryOp::Pow),
+    ];
+    levels.sort_by_key(|(_, op)| op.precedence());
+    levels.into_iter().fold(PrattParser::new(), |pratt, (rule, op)| {
+        let assoc = match op.associativity() {
+            Associativity::Left => Assoc::Left,
+            Associativity::Right => Assoc::Right,
+        };
+        pratt.op(Op::infix(rule, assoc))
+    })
 }
 
-fn parse_expr(pair: Pair<Rule>) -> Result<Expr> {
+fn parse_expr(pair: Pair<Rule>) -> Result<Spanned> {
     match pair.as_rule() {
-        Rule::expr => {
+        // the value of a `let` is an expr that stops before `in`
+        Rule::expr | Rule::let_value => {
             let pairs = pair.into_inner();
-            pratt()
-                .map_primary(|p: Pair<Rule>| parse_unary(p))
-                .map_infix(|lhs: Result<Expr>, op: Pair<Rule>, rhs: Result<Expr>| {
-                    let left = lhs?;
-                    let right = rhs?;
-                    let mut l = left;
-                    let mut r = right;
-                    let bop = match op.as_rule() {
-                        Rule::op_or => BinaryOp::Or,
-                        Rule::op_and => BinaryOp::And,
-                        Rule::op_eq => {
-                            let s = op.as_str();
-                            if s.contains("==") { BinaryOp::Eq } else { BinaryOp::Ne }
-                        }
-                        Rule::op_cmp => {
-                            let s = op.as_str();
-                            if s.contains("<=") {
-                                // a <= b  ==>  b >= a
-                                std::mem::swap(&mut l, &mut r);
-                                BinaryOp::Ge
-                            } else if s.contains(">=") {
-                                BinaryOp::Ge
-                            } else if s.contains('<') {
-                                BinaryOp::Lt
-                            } else {
-                                BinaryOp::Gt
+            // each operand comes with whether it is a comparison that a following comparison extends into a chain,
+            // which one in parentheses is not
+            PRATT.with(|pratt| {
+                pratt
+                    .map_primary(|p: Pair<Rule>| {
+                        let operand = match p.as_rule() {
+                            Rule::let_lambda => parse_lambda(p),
+                            Rule::let_inner => parse_let(p),
+                            _ => parse_unary(p),
+                        };
+                        Ok((operand?, false))
+                    })
+                    .map_infix(|lhs: Result<(Spanned, bool)>, op: Pair<Rule>, rhs: Result<(Spanned, bool)>| {
+                        let ((l, chains), (r, _)) = (lhs?, rhs?);
+                        let span = l.1[0].start..r.1[0].end;
+                        let bop = match op.as_rule() {
+                            Rule::op_or => BinaryOp::Or,
+                            Rule::op_and => BinaryOp::And,
+                            Rule::op_eq => {
+                                let s = op.as_str();
+                                if s.contains("==") { BinaryOp::Eq } else { BinaryOp::Ne }
                             }
-                        }
-                        Rule::op_add => {
-                            if op.as_str().contains('-') {
-                                BinaryOp::Sub
-                            } else {
-                                BinaryOp::Add
+                            Rule::op_cmp => {
+                                let s = op.as_str();
+                                if s == "in" {
+                                    BinaryOp::In
+                                } else if s.contains("<=") {
+                                    BinaryOp::Le
+                                } else if s.contains(">=") {
+                                    BinaryOp::Ge
+                                } else if s.contains('<') {
+                                    BinaryOp::Lt
+                                } else {
+                                    BinaryOp::Gt
+                                }
                             }
-                        }
-                        Rule::op_mul => {
-                            let s = op.as_str();
-                            if s.contains('*') {
-                                BinaryOp::Mul
-                            } else if s.contains('/') {
-                                BinaryOp::Div
-                            } else {
-                                BinaryOp::Mod
+                            Rule::op_range => BinaryOp::Range,
+                            Rule::op_bitor => BinaryOp::BitOr,
+                            Rule::op_bitxor => BinaryOp::BitXor,
+                            Rule::op_bitand => BinaryOp::BitAnd,
+                            Rule::op_shift => {
+                                if op.as_str() == "<<" {
+                                    BinaryOp::Shl
+                                } else {
+                                    BinaryOp::Shr
+                                }
                             }
+                            Rule::op_add => {
+                                if op.as_str().contains('-') {
+                                    BinaryOp::Sub
+                                } else {
+                                    BinaryOp::Add
+                                }
+                            }
+                            Rule::op_mul => {
+                                let s = op.as_str();
+                                if s.contains('*') {
+                                    BinaryOp::Mul
+                                } else if s.contains('/') {
+                                    BinaryOp::Div
+                                } else {
+                                    BinaryOp::Mod
+                                }
+                            }
+                            Rule::op_pow => BinaryOp::Pow,
+                            r => {
+                                return Err(Error::InternalParserError(format!("unexpected infix op: {:?}", r)));
+                            }
+                        };
+                        if chains && bop.is_comparison() {
+                            let (first, mut rest) = match l.0 {
+                                Expr::Binary { op, left, right } => (left, vec![(op, *right)]),
+                                Expr::Compare { first, rest } => (first, rest),
+                                other => return Err(Error::InternalParserError(format!("not a comparison: {:?}", other))),
+                            };
+                            rest.push((bop, r.0));
+                            // the chain takes the place of its first comparison, whose operands keep their spans
+                            let mut spans = l.1;
+                            spans[0] = span;
+                            spans.extend(r.1);
+                            return Ok(((Expr::Compare { first, rest }, spans), true));
                         }
-                        Rule::op_pow => BinaryOp::Pow,
-                        r => {
-                            return Err(Error::InternalParserError(format!("unexpected infix op: {:?}", r)));
-                        }
-                    };
-                    Ok(Expr::Binary {
-                        left: Box::new(l),
-                        op: bop,
-                        right: Box::new(r),
+                        let binary = Expr::Binary {
+                            left: Box::new(l.0),
+                            op: bop,
+                            right: Box::new(r.0),
+                        };
+                        Ok((node(binary, span, [l.1, r.1]), bop.is_comparison()))
                     })
-                })
-                .parse(pairs)
+                    .parse(pairs)
+                    .map(|(expr, _)| expr)
+            })
         }
+        Rule::assignment => parse_assignment(pair),
         _ => Err(Error::InternalParserError(format!("expected expr, got: {:?}", pair))),
     }
 }
 
-fn parse_unary(pair: Pair<Rule>) -> Result<Expr> {
+fn parse_unary(pair: Pair<Rule>) -> Result<Spanned> {
     match pair.as_rule() {
         Rule::unary => {
-            let mut ops: Vec<UnaryOp> = Vec::new();
+            // each operator with the position it starts at
+            let mut ops: Vec<(UnaryOp, usize)> = Vec::new();
             let mut inner = pair.into_inner();
             // Collect zero or more unary_op then the postfix expression
-            loop {
-                let Some(next) = inner.peek() else { break };
+            while let Some(next) = inner.peek() {
                 match next.as_rule() {
                     Rule::unary_op => {
                         let op_pair = inner.next().unwrap();
+                        let start = op_pair.as_span().start();
                         let op_inner = op_pair.into_inner().next().unwrap();
                         let op = match op_inner.as_rule() {
                             Rule::not_op => UnaryOp::Not,
@@ -120,15 +319,16 @@ fn parse_unary(pair: Pair<Rule>) -> Result<Expr> {
                                 return Err(Error::InternalParserError(format!("unexpected unary op: {:?}", r)));
                             }
                         };
-                        ops.push(op);
+                        ops.push((op, start));
                     }
                     _ => break,
                 }
             }
             let post = inner.next().expect("unary must end with postfix");
             let mut expr = parse_postfix(post)?;
-            for op in ops.into_iter().rev() {
-                expr = Expr::Unary { op, expr: Box::new(expr) };
+            let end = expr.1[0].end;
+            for (op, start) in ops.into_iter().rev() {
+                expr = node(Expr::Unary { op, expr: Box::new(expr.0) }, start..end, [expr.1]);
             }
             Ok(expr)
         }
@@ -136,29 +336,32 @@ fn parse_unary(pair: Pair<Rule>) -> Result<Expr> {
     }
 }
 
-fn parse_postfix(pair: Pair<Rule>) -> Result<Expr> {
+fn parse_postfix(pair: Pair<Rule>) -> Result<Spanned> {
     match pair.as_rule() {
         Rule::postfix => {
             let mut inner = pair.into_inner();
             let first = inner.next().expect("postfix starts with primary");
+            let start = first.as_span().start();
             let mut expr = parse_primary(first)?;
             for next in inner {
+                let span = start..next.as_span().end();
                 match next.as_rule() {
                     Rule::call => {
-                        let args = parse_call_args(next)?;
-                        expr = Expr::Call { callee: Box::new(expr), args };
+                        let (args, arg_spans): (Vec<_>, Vec<_>) = parse_call_args(next)?.into_iter().unzip();
+                        expr = node(Expr::Call { callee: Box::new(expr.0), args }, span, std::iter::once(expr.1).chain(arg_spans));
                     }
                     Rule::index => {
                         let idx_pair = next.into_inner().next().expect("index inner expr");
                         let index_expr = parse_expr(idx_pair)?;
-                        expr = Expr::Index {
-                            object: Box::new(expr),
-                            index: Box::new(index_expr),
+                        let indexed = Expr::Index {
+                            object: Box::new(expr.0),
+                            index: Box::new(index_expr.0),
                         };
+                        expr = node(indexed, span, [expr.1, index_expr.1]);
                     }
                     Rule::property => {
                         let name = next.into_inner().next().expect("property ident").as_str().to_string();
-                        expr = Expr::Member { object: Box::new(expr), field: name };
+                        expr = node(Expr::Member { object: Box::new(expr.0), field: name }, span, [expr.1]);
                     }
                     r => {
                         return Err(Error::InternalParserError(format!("unexpected postfix op: {:?}", r)));
@@ -171,7 +374,10 @@ fn parse_postfix(pair: Pair<Rule>) -> Result<Expr> {
     }
 }
 
-fn parse_call_args(pair: Pair<Rule>) -> Result<Vec<Expr>> {
+/// Name of the variable that `@` parses to. It cannot clash with an identifier.
+pub const CURRENT: &str = "@";
+
+fn parse_call_args(pair: Pair<Rule>) -> Result<Vec<Spanned>> {
     debug_assert_eq!(pair.as_rule(), Rule::call);
     let mut args = Vec::new();
     for p in pair.into_inner() {
@@ -183,88 +389,182 @@ fn parse_call_args(pair: Pair<Rule>) -> Result<Vec<Expr>> {
     Ok(args)
 }
 
-fn parse_primary(pair: Pair<Rule>) -> Result<Expr> {
+fn parse_primary(pair: Pair<Rule>) -> Result<Spanned> {
+    let span = span_of(&pair);
+    let leaf = |expr: Expr| Ok(node(expr, span.clone(), []));
     match pair.as_rule() {
         Rule::primary => parse_primary(pair.into_inner().next().unwrap()),
-        Rule::parens => parse_expr(pair.into_inner().next().unwrap()),
-        Rule::ident => Ok(Expr::Var(pair.as_str().to_string())),
-        Rule::number => parse_number(pair),
+        Rule::parens => {
+            // the parentheses belong to the span, so that a binary operation starting with them covers them too
+            let (expr, mut spans) = parse_expr(pair.into_inner().next().unwrap())?;
+            spans[0] = span;
+            Ok((expr, spans))
+        }
+        Rule::ident => leaf(Expr::Var(pair.as_str().to_string())),
+        Rule::current => leaf(Expr::Var(CURRENT.to_string())),
+        Rule::number => leaf(parse_number(pair)?),
         Rule::boolean => {
             let inner = pair.into_inner().next().unwrap();
             let val = matches!(inner.as_rule(), Rule::true_kw);
-            Ok(Expr::Literal(Primitive::Bool(val)))
+            leaf(Expr::Literal(Primitive::Bool(val)))
         }
+        Rule::null_kw => leaf(Expr::Literal(Primitive::Null)),
         Rule::string => {
             let s = unescape_string(pair.as_str())?;
-            Ok(Expr::Literal(Primitive::Str(s)))
+            leaf(Expr::Literal(Primitive::Str(s)))
+        }
+        Rule::list => {
+            let (items, spans): (Vec<_>, Vec<_>) = parse_exprs(pair)?.into_iter().unzip();
+            Ok(node(Expr::ListLiteral(items), span, spans))
         }
-        Rule::list => parse_list(pair),
         Rule::dict => parse_dict(pair),
+        Rule::set => {
+            let (items, spans): (Vec<_>, Vec<_>) = parse_exprs(pair)?.into_iter().unzip();
+            Ok(node(Expr::SetLiteral(items), span, spans))
+        }
+        Rule::lambda => parse_lambda(pair),
+        Rule::let_expr => parse_let(pair),
         r => Err(Error::InternalParserError(format!("unexpected primary op: {:?}", r))),
     }
 }
 
 fn parse_number(pair: Pair<Rule>) -> Result<Expr> {
     let inner = pair.into_inner().next().unwrap();
+    // digit separators are only for the reader
+    let text = inner.as_str().replace('_', "");
     match inner.as_rule() {
         Rule::int => {
-            let s = inner.as_str();
+            let s = text.as_str();
             let v: i64 = s.parse().map_err(|_| Error::ParseError(format!("invalid int: {}", s)))?;
             Ok(Expr::Literal(Primitive::Int(v)))
         }
+        Rule::radix_int => {
+            let s = text.as_str();
+            let radix = match &s[1..2] {
+                "x" | "X" => 16,
+                "o" | "O" => 8,
+                _ => 2,
+            };
+            let v = i64::from_str_radix(&s[2..], radix).map_err(|_| Error::ParseError(format!("int literal out of range: {}", inner.as_str())))?;
+            Ok(Expr::Literal(Primitive::Int(v)))
+        }
         Rule::float => {
-            let s = inner.as_str();
+            let s = text.as_str();
             let v: f64 = s.parse().map_err(|_| Error::ParseError(format!("invalid float: {}", s)))?;
             Ok(Expr::Literal(Primitive::Float(v)))
         }
+        #[cfg(feature = "decimal")]
+        Rule::decimal => {
+            let s = text.trim_end_matches('m');
+            let v: rust_decimal::Decimal = s.parse().map_err(|_| Error::ParseError(format!("invalid decimal: {}m", s)))?;
+            Ok(Expr::Literal(Primitive::Decimal(v)))
+        }
+        #[cfg(not(feature = "decimal"))]
+        Rule::decimal => Err(Error::ParseError(format!("decimal literal {} requires the 'decimal' feature", inner.as_str()))),
         r => Err(Error::InternalParserError(format!("unexpected number: {:?}", r))),
     }
 }
 
-fn parse_list(pair: Pair<Rule>) -> Result<Expr> {
+// the comma-separated expressions of a list or set literal
+fn parse_exprs(pair: Pair<Rule>) -> Result<Vec<Spanned>> {
     let mut elems = Vec::new();
     for p in pair.into_inner() {
         if let Rule::expr = p.as_rule() {
             elems.push(parse_expr(p)?);
         }
     }
-    Ok(Expr::ListLiteral(elems))
+    Ok(elems)
+}
+
+fn parse_lambda(pair: Pair<Rule>) -> Result<Spanned> {
+    let span = span_of(&pair);
+    let mut params: Vec<String> = Vec::new();
+    let mut body = None;
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::ident if params.iter().any(|name| name == p.as_str()) => return Err(Error::ParseError(format!("duplicate parameter '{}'", p.as_str()))),
+            Rule::ident => params.push(p.as_str().to_string()),
+            _ => body = Some(parse_expr(p)?),
+        }
+    }
+    let (body, body_spans) = body.ok_or_else(|| Error::InternalParserError("lambda without a body".into()))?;
+    Ok(node(Expr::Lambda { params, body: Box::new(body) }, span, [body_spans]))
 }
 
-fn parse_dict(pair: Pair<Rule>) -> Result<Expr> {
+fn parse_let(pair: Pair<Rule>) -> Result<Spanned> {
+    let span = span_of(&pair);
+    let mut inner = pair.into_inner().filter(|p| !matches!(p.as_rule(), Rule::let_kw | Rule::in_kw));
+    let (Some(name), Some(value), Some(body)) = (inner.next(), inner.next(), inner.next()) else {
+        return Err(Error::InternalParserError("incomplete let".into()));
+    };
+    let (value, body) = (parse_expr(value)?, parse_expr(body)?);
+    let bound = Expr::Let {
+        name: name.as_str().to_string(),
+        value: Box::new(value.0),
+        body: Box::new(body.0),
+    };
+    Ok(node(bound, span, [value.1, body.1]))
+}
+
+fn parse_assignment(pair: Pair<Rule>) -> Result<Spanned> {
+    let span = span_of(&pair);
+    let mut inner = pair.into_inner().filter(|p| p.as_rule() != Rule::assign_op);
+    let (Some(target), Some(value)) = (inner.next(), inner.next()) else {
+        return Err(Error::InternalParserError("incomplete assignment".into()));
+    };
+    let target_source = target.as_str();
+    let (target, mut target_spans) = parse_postfix(target)?;
+    let Expr::Member { object, field } = target else {
+        return Err(Error::ParseError(format!("cannot assign to {}: only members such as obj.field can be assigned", target_source.trim())));
+    };
+    let (value, value_spans) = parse_expr(value)?;
+    // the member node itself is replaced by the assignment, its object's spans stay
+    target_spans.remove(0);
+    Ok(node(Expr::Assign { object, field, value: Box::new(value) }, span, [target_spans, value_spans]))
+}
+
+fn parse_dict(pair: Pair<Rule>) -> Result<Spanned> {
+    let span = span_of(&pair);
     let mut items = Vec::new();
+    let mut spans = Vec::new();
     for p in pair.into_inner() {
         if let Rule::pair = p.as_rule() {
             let mut it = p.into_inner();
             let key_pair = it.next().expect("pair key expr");
-            let key = parse_expr(key_pair)?;
+            let (key, key_spans) = parse_expr(key_pair)?;
             let value_pair = it.next().expect("pair value expr");
-            let value = parse_expr(value_pair)?;
+            let (value, value_spans) = parse_expr(value_pair)?;
             items.push((key, value));
+            spans.extend([key_spans, value_spans]);
         }
     }
-    Ok(Expr::DictLiteral(items))
+    Ok(node(Expr::DictLiteral(items), span, spans))
 }
 
 fn unescape_string(src: &str) -> Result<String> {
-    // strip surrounding quotes if present (supports both ' and ")
-    // let raw = if src.starts_with('"') && src.ends_with('"') && src.len() >= 2 {
-    //     &src[1..src.len() - 1]
-    // } else if src.starts_with('\'') && src.ends_with('\'') && src.len() >= 2 {
-    //     &src[1..src.len() - 1]
-    // } else {
-    //     src
-    // };
     let escape_char = src.chars().next().unwrap();
     let mut out = String::with_capacity(src.len() - 2);
-    let mut chars = src[1..src.len() - 1].chars().peekable();
+    let mut chars = src[1..src.len() - 1].chars();
     while let Some(c) = chars.next() {
         if c == '\\' {
             match chars.next() {
                 Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('r') => out.push('\r'),
+                Some('0') => out.push('\0'),
                 Some('\\') => out.push('\\'),
+                Some('u') => {
+                    // exactly four hex digits naming a Unicode scalar value (so not a lone surrogate)
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 4 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+                    match code.and_then(char::from_u32) {
+                        Some(ch) => out.push(ch),
+                        None => return Err(Error::ParseError(format!("invalid unicode escape \\u{}", hex))),
+                    }
+                }
                 Some(next) if next == escape_char => out.push(escape_char),
-                _ => return Err(Error::ParseError(format!("invalid escape character {}", c))),
+                Some(next) => return Err(Error::ParseError(format!("invalid escape \\{}", next))),
+                None => return Err(Error::ParseError("unterminated escape".into())),
             }
         } else {
             out.push(c);
@@ -280,8 +580,425 @@ mod tests {
     #[test]
     fn test_interpolated_expr() {
         let input = "123}x";
-        let (expr, idx) = parse_internal(input, Rule::delimited_expr).unwrap();
+        let ((expr, spans), idx) = parse_internal(input, Rule::delimited_expr, DEFAULT_MAX_NESTING).unwrap();
+        assert_eq!(spans, vec![0..3]);
         assert_eq!(expr, Expr::Literal(Primitive::Int(123)));
         assert_eq!(idx, 4);
     }
+
+    #[test]
+    fn test_spans() {
+        struct Nodes(Vec<Expr>);
+        impl crate::types::visitor::Visitor for Nodes {
+            fn visit_expr(&mut self, expr: &Expr) {
+                self.0.push(expr.clone());
+                crate::types::visitor::walk_expr(self, expr);
+            }
+        }
+
+        let src = "-(a + b) * f(x, [1, 'two'])[0].y + {'k': {3}}.keys() + (|p| p ^ 2)(4) + let v = !w in v";
+        let (expr, spans) = parse_spanned(src).unwrap();
+        let mut nodes = Nodes(Vec::new());
+        crate::types::visitor::Visitor::visit_expr(&mut nodes, &expr);
+        assert_eq!(spans.len(), nodes.0.len());
+        // every node spans exactly its own source text
+        for (node, span) in nodes.0.iter().zip(&spans) {
+            assert_eq!(&parse_expression(&src[span.clone()]).unwrap(), node, "span {:?} is '{}'", span, &src[span.clone()]);
+        }
+        assert_eq!(&src[spans[3].clone()], "-(a + b) * f(x, [1, 'two'])[0].y");
+        // a comparison chain is one node, with its operands in source order
+        let (expr, spans) = parse_spanned("aa <= b < c").unwrap();
+        assert_eq!(spans, vec![0..11, 0..2, 6..7, 10..11]);
+        let Expr::Compare { first, rest } = expr else {
+            panic!("not a chain: {:?}", expr);
+        };
+        assert_eq!(*first, Expr::Var("aa".into()));
+        assert_eq!(rest.iter().map(|(op, _)| *op).collect::<Vec<_>>(), vec![BinaryOp::Le, BinaryOp::Lt]);
+        // parentheses end a chain
+        assert!(matches!(parse_expression("(a < b) < c").unwrap(), Expr::Binary { op: BinaryOp::Lt, .. }));
+        assert!(matches!(parse_expression("a < (b < c)").unwrap(), Expr::Binary { op: BinaryOp::Lt, .. }));
+        assert!(matches!(parse_expression("(a < b < c)").unwrap(), Expr::Compare { .. }));
+    }
+
+    #[test]
+    fn test_tokenize() {
+        use TokenKind::*;
+        fn kinds(input: &str) -> Vec<(TokenKind, &str)> {
+            tokenize(input).unwrap().into_iter().map(|t| (t.kind, &input[t.start..t.end])).collect()
+        }
+        assert_eq!(
+            kinds("a.b(1, 'x') >= 2.5 // done"),
+            vec![
+                (Ident, "a"),
+                (Punctuation, "."),
+                (Ident, "b"),
+                (Punctuation, "("),
+                (Number, "1"),
+                (Punctuation, ","),
+                (String, "'x'"),
+                (Punctuation, ")"),
+                (Operator, ">="),
+                (Number, "2.5"),
+            ]
+        );
+        assert_eq!(
+            kinds("!true and {'k': [null]}"),
+            vec![
+                (Operator, "!"),
+                (Keyword, "true"),
+                (Operator, "and"),
+                (Punctuation, "{"),
+                (String, "'k'"),
+                (Punctuation, ":"),
+                (Punctuation, "["),
+                (Keyword, "null"),
+                (Punctuation, "]"),
+                (Punctuation, "}")
+            ]
+        );
+        // incomplete input that would not parse can still be tokenized
+        assert!(parse_expression("foo(1, ").is_err());
+        assert_eq!(kinds("foo(1, "), vec![(Ident, "foo"), (Punctuation, "("), (Number, "1"), (Punctuation, ",")]);
+        assert_eq!(kinds("x + 'abc"), vec![(Ident, "x"), (Operator, "+"), (Unknown, "'abc")]);
+        assert_eq!(kinds("a ? b"), vec![(Ident, "a"), (Unknown, "?"), (Ident, "b")]);
+        assert_eq!(kinds("xs.map(@)"), vec![(Ident, "xs"), (Punctuation, "."), (Ident, "map"), (Punctuation, "("), (Ident, "@"), (Punctuation, ")")]);
+        assert_eq!(kinds(""), vec![]);
+    }
+
+    #[test]
+    fn test_keyword_operators() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        assert_eq!(parse_expression("a and b").unwrap(), parse_expression("a && b").unwrap());
+        assert_eq!(parse_expression("a or b").unwrap(), parse_expression("a || b").unwrap());
+        assert_eq!(parse_expression("not a").unwrap(), parse_expression("!a").unwrap());
+        assert_eq!(parse_expression("a <> b").unwrap(), parse_expression("a != b").unwrap());
+        // keywords only count as operators on a word boundary
+        assert_eq!(
+            parse_expression("android and notable").unwrap(),
+            Expr::Binary {
+                left: var("android"),
+                op: BinaryOp::And,
+                right: var("notable"),
+            }
+        );
+        assert_eq!(parse_expression("order").unwrap(), Expr::Var("order".to_string()));
+        assert_eq!(parse_expression("trueValue").unwrap(), Expr::Var("trueValue".to_string()));
+        assert_eq!(
+            parse_expression("index in inputs").unwrap(),
+            Expr::Binary {
+                left: var("index"),
+                op: BinaryOp::In,
+                right: var("inputs"),
+            }
+        );
+        assert_eq!(parse_expression("null").unwrap(), Expr::Literal(Primitive::Null));
+        assert_eq!(parse_expression("nullable").unwrap(), Expr::Var("nullable".to_string()));
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let src = |s: &str| parse_expression(s).unwrap().to_source();
+        assert_eq!(src("a&b|c~d<<e>>f"), "a & b | c ~ d << e >> f");
+        // `&&` and `||` still win over `&` and `|`, and shifts over comparisons
+        assert!(matches!(parse_expression("a && b || c").unwrap(), Expr::Binary { op: BinaryOp::Or, .. }));
+        assert!(matches!(parse_expression("a << b").unwrap(), Expr::Binary { op: BinaryOp::Shl, .. }));
+        assert!(matches!(parse_expression("a >> b").unwrap(), Expr::Binary { op: BinaryOp::Shr, .. }));
+        // looser than arithmetic, tighter than comparison; `|` < `~` < `&` < shifts among themselves
+        assert_eq!(src("(1 + 2) << 3"), "1 + 2 << 3");
+        assert_eq!(src("(a & 1) == 0"), "a & 1 == 0");
+        assert_eq!(src("a | (b ~ (c & (d << 1)))"), "a | b ~ c & d << 1");
+        assert_eq!(src("(a | b) & c"), "(a | b) & c");
+    }
+
+    #[test]
+    fn test_range_operator() {
+        let src = |s: &str| parse_expression(s).unwrap().to_source();
+        assert!(matches!(parse_expression("1..5").unwrap(), Expr::Binary { op: BinaryOp::Range, .. }));
+        assert_eq!(src("a ..b"), "a .. b");
+        // looser than arithmetic, tighter than `in` and comparisons
+        assert_eq!(src("x in (0 .. (n + 1))"), "x in 0 .. n + 1");
+        assert_eq!(src("(-3) .. (x.length)"), "-3 .. x.length");
+        assert_eq!(src("(0 .. 5)[1]"), "(0 .. 5)[1]");
+        // `..` never starts a float or a member access
+        assert_eq!(src("1..2"), "1 .. 2");
+        assert_eq!(src("1...2"), "1 .. 0.2");
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        assert_eq!(parse_expression("[1, 2, 3,]").unwrap(), parse_expression("[1, 2, 3]").unwrap());
+        assert_eq!(parse_expression("{\"a\": 1,}").unwrap(), parse_expression("{\"a\": 1}").unwrap());
+        assert_eq!(parse_expression("f(a, b,)").unwrap(), parse_expression("f(a, b)").unwrap());
+        for src in ["[,1]", "[,]", "{,}", "f(,)", "f(,a)", "f(a,,)", "[1,,]"] {
+            assert!(parse_expression(src).is_err(), "'{}' should not parse", src);
+        }
+    }
+
+    #[test]
+    fn test_rejects_incomplete_or_trailing_input() {
+        for src in ["1 2", "1 +", "(1", "1 + 2 )", "a.b c", "[1] ]", ""] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "'{}' should not parse", src);
+        }
+        assert!(parse_expression("  1 + 2  // trailing comment").is_ok());
+    }
+
+    #[test]
+    fn test_comments() {
+        let expected = parse_expression("a + b * 2").unwrap();
+        for src in [
+            "a + b * 2 // trailing",
+            "a + b * 2 # trailing",
+            "a + b * 2 /* trailing */",
+            "a /* one */ + /* two */ b * 2",
+            "# leading line\na + // after plus\n  b * 2",
+            "/* spans\n lines */ a + b/**/*2",
+            "a + b * 2 //",
+        ] {
+            assert_eq!(parse_expression(src).unwrap(), expected, "{:?}", src);
+        }
+        // comment markers inside strings are text
+        for text in ["// not", "# not", "/* not */"] {
+            let src = format!("'{}' # comment", text);
+            assert_eq!(parse_expression(&src).unwrap(), Expr::Literal(Primitive::Str(text.to_string())));
+        }
+        // block comments do not nest and must be closed
+        for src in ["1 /* open", "1 /* a /* b */ */"] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "'{}' should not parse", src);
+        }
+    }
+
+    #[test]
+    fn test_set_or_dict_literal() {
+        let int = |i: i64| Expr::Literal(Primitive::Int(i));
+        assert_eq!(parse_expression("{1, 2,}").unwrap(), Expr::SetLiteral(vec![int(1), int(2)]));
+        assert_eq!(parse_expression("{x}").unwrap(), Expr::SetLiteral(vec![Expr::Var("x".to_string())]));
+        assert_eq!(parse_expression("{'a': 1}").unwrap(), Expr::DictLiteral(vec![(Expr::Literal(Primitive::Str("a".to_string())), int(1))]));
+        assert!(matches!(parse_expression("{{1}: {'k': {2}}}").unwrap(), Expr::DictLiteral(_)));
+        assert_eq!(parse_expression("{ 1 , 2 }").unwrap().to_source(), "{1, 2}");
+        // every element is either a key-value pair or a plain expression
+        for src in ["{1: 2, 3}", "{1, 2: 3}", "{,}"] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "'{}' should not parse", src);
+        }
+    }
+
+    #[test]
+    fn test_empty_braces_are_a_dict() {
+        assert_eq!(parse_expression("{}").unwrap(), Expr::DictLiteral(vec![]));
+        assert_eq!(parse_expression("{ }").unwrap(), Expr::DictLiteral(vec![]));
+        assert_eq!(
+            parse_expression("!{}").unwrap(),
+            Expr::Unary {
+                op: UnaryOp::Not,
+                expr: Box::new(Expr::DictLiteral(vec![])),
+            }
+        );
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        let int = |src: &str| parse_expression(src).unwrap();
+        assert_eq!(int("0xFF"), Expr::Literal(Primitive::Int(255)));
+        assert_eq!(int("0X1f"), Expr::Literal(Primitive::Int(31)));
+        assert_eq!(int("0o17"), Expr::Literal(Primitive::Int(15)));
+        assert_eq!(int("0b1010"), Expr::Literal(Primitive::Int(10)));
+        assert_eq!(int("0x7FFFFFFFFFFFFFFF"), Expr::Literal(Primitive::Int(i64::MAX)));
+        assert_eq!(int("-0x10"), int("-16"));
+        assert_eq!(int("010"), Expr::Literal(Primitive::Int(10)));
+        for src in ["0xZZ", "0x", "0b102", "0o8", "0x1g"] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "'{}' should not parse", src);
+        }
+        match parse_expression("0x8000000000000000") {
+            Err(Error::ParseError(msg)) => assert!(msg.contains("0x8000000000000000"), "{}", msg),
+            other => panic!("expected ParseError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lambda() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        let lambda = |params: &[&str], body: Box<Expr>| Expr::Lambda {
+            params: params.iter().map(|p| p.to_string()).collect(),
+            body,
+        };
+        assert_eq!(parse_expression("|x| x").unwrap(), lambda(&["x"], var("x")));
+        assert_eq!(parse_expression("|| x").unwrap(), lambda(&[], var("x")));
+        // the body takes everything to its right, including a bitwise or
+        let body = Box::new(Expr::Binary {
+            op: BinaryOp::BitOr,
+            left: var("a"),
+            right: var("b"),
+        });
+        assert_eq!(parse_expression("|a, b,| a | b").unwrap(), lambda(&["a", "b"], body));
+        assert!(matches!(parse_expression("f(|x| x, 1)").unwrap(), Expr::Call { args, .. } if args.len() == 2));
+        for src in ["|x, x| x", "|x|", "|1| x", "|x x"] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "'{}' should not parse", src);
+        }
+    }
+
+    #[test]
+    fn test_let() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        let membership = Box::new(Expr::Binary {
+            op: BinaryOp::In,
+            left: var("a"),
+            right: var("b"),
+        });
+        // the value ends at the first `in`; the body takes the rest
+        assert_eq!(
+            parse_expression("let a = x in a in b").unwrap(),
+            Expr::Let {
+                name: "a".to_string(),
+                value: var("x"),
+                body: membership.clone(),
+            }
+        );
+        assert_eq!(
+            parse_expression("let a = (a in b) in a").unwrap(),
+            Expr::Let {
+                name: "a".to_string(),
+                value: membership,
+                body: var("a"),
+            }
+        );
+        assert_eq!(parse_expression("letter + lets").unwrap().free_vars().len(), 2);
+        for src in ["let a = 1", "let a in a", "let 1 = 1 in 1", "let a == 1 in a"] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "'{}' should not parse", src);
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let too_deep = |src: &str| matches!(parse_expression(src), Err(Error::ParseError(message)) if message.contains("nested too deeply"));
+        let n = 100_000;
+        for src in [
+            "(".repeat(n),
+            format!("{}1{}", "[".repeat(n), "]".repeat(n)),
+            format!("{}1{}", "(-".repeat(n), ")".repeat(n)),
+            format!("{}1", "-".repeat(n)),
+            format!("{}1", "|x| ".repeat(n)),
+            format!("{}1", "let a = 1 in ".repeat(n)),
+            format!("1{}", " ^ 1".repeat(n)),
+            format!("a{}", ".b".repeat(n)),
+            format!("f{}", "(1)".repeat(n)),
+        ] {
+            assert!(too_deep(&src), "'{}...' should be rejected", &src[..20]);
+        }
+        // the limit is on depth, not length
+        assert!(parse_expression(&format!("{}1{}", "[".repeat(DEFAULT_MAX_NESTING), "]".repeat(DEFAULT_MAX_NESTING))).is_ok());
+        assert!(too_deep(&format!("{}1{}", "[".repeat(DEFAULT_MAX_NESTING + 1), "]".repeat(DEFAULT_MAX_NESTING + 1))));
+        // and can be raised or lowered
+        let nested = |n: usize| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        assert!(parse_expression_with_max_nesting(&nested(DEFAULT_MAX_NESTING + 1), DEFAULT_MAX_NESTING + 1).is_ok());
+        assert!(matches!(parse_expression_with_max_nesting(&nested(3), 2), Err(Error::ParseError(message)) if message.contains("nested too deeply")));
+        assert!(parse_expression(&format!("1{}", " + 1".repeat(400))).is_ok());
+        assert!(parse_expression(&format!("[{}]", "-1 + 1, ".repeat(1000))).is_ok());
+        assert!(parse_expression(&format!("f({})", "|a, b| a - b, ".repeat(1000))).is_ok());
+        // brackets in strings and comments do not nest
+        let brackets = "([{".repeat(1000);
+        assert!(parse_expression(&format!("'{0}' + \"{0}\" /* {0} */ // {0}", brackets)).is_ok());
+    }
+
+    #[test]
+    fn test_assignment() {
+        let (expr, spans) = parse_spanned("a.b.c = 1 + 2").unwrap();
+        let Expr::Assign { object, field, value } = &expr else {
+            panic!("not an assignment: {:?}", expr);
+        };
+        assert_eq!(**object, parse_expression("a.b").unwrap());
+        assert_eq!(field, "c");
+        assert_eq!(**value, parse_expression("1 + 2").unwrap());
+        // the assignment, a.b, a, 1 + 2, 1, 2
+        assert_eq!(spans, vec![0..13, 0..3, 0..1, 8..13, 8..9, 12..13]);
+        // comparisons are not assignments
+        assert!(matches!(parse_expression("a.b == 1").unwrap(), Expr::Binary { op: BinaryOp::Eq, .. }));
+        for src in ["x = 1", "a[0] = 1", "f() = 1", "a.b = ", "1 + a.b = 2", "[a.b = 1]", "(a.b = 1)", "a.b = c.d = 1"] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "'{}' should not parse", src);
+        }
+        assert!(parse_expression("x = 1").unwrap_err().to_string().contains("cannot assign to x"));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let string = |src: &str| match parse_expression(src) {
+            Ok(Expr::Literal(Primitive::Str(s))) => s,
+            other => panic!("'{}' is not a string literal: {:?}", src, other),
+        };
+        assert_eq!(string(r#""a\tb\rc\nd""#), "a\tb\rc\nd");
+        assert_eq!(string(r#""nul\0""#), "nul\0");
+        assert_eq!(string(r#""\u0041""#), "A");
+        assert_eq!(string(r#"'\u00e9\u20AC'"#), "é€");
+        assert_eq!(string(r#"'it\'s \\ ok'"#), "it's \\ ok");
+        for src in [r#""\uZZZZ""#, r#""\u12""#, r#""\uD800""#, r#""\q""#, r#"'\"'"#] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "{} should not parse", src);
+        }
+    }
+
+    #[test]
+    fn test_exponent_literals() {
+        let lit = |src: &str| parse_expression(src).unwrap();
+        assert_eq!(lit("1e9"), Expr::Literal(Primitive::Float(1e9)));
+        assert_eq!(lit("2.5e-3"), Expr::Literal(Primitive::Float(2.5e-3)));
+        assert_eq!(lit("6.02E23"), Expr::Literal(Primitive::Float(6.02e23)));
+        assert_eq!(lit("1e+2"), Expr::Literal(Primitive::Float(100.0)));
+        assert_eq!(lit(".5e1"), Expr::Literal(Primitive::Float(5.0)));
+        // integral, but still a float
+        assert_eq!(lit("1e3"), Expr::Literal(Primitive::Float(1000.0)));
+        for src in ["1e", "1e+", "1.5E-"] {
+            assert!(parse_expression(src).is_err(), "'{}' should not parse", src);
+        }
+    }
+
+    #[test]
+    fn test_digit_separators() {
+        let lit = |src: &str| parse_expression(src).unwrap();
+        assert_eq!(lit("1_000_000"), Expr::Literal(Primitive::Int(1_000_000)));
+        assert_eq!(lit("1.234_567"), Expr::Literal(Primitive::Float(1.234_567)));
+        assert_eq!(lit("1_0.5"), Expr::Literal(Primitive::Float(10.5)));
+        assert_eq!(lit("0xFF_FF"), Expr::Literal(Primitive::Int(0xFFFF)));
+        assert_eq!(lit("0b1010_1010"), Expr::Literal(Primitive::Int(0b1010_1010)));
+        for src in ["1_", "1__0", "1_.5", "0x_FF", "2_e3"] {
+            assert!(matches!(parse_expression(src), Err(Error::ParseError(_))), "'{}' should not parse", src);
+        }
+        // a leading underscore makes an identifier
+        assert_eq!(lit("_1"), Expr::Var("_1".to_string()));
+    }
+
+    #[test]
+    fn test_decimal_literal() {
+        #[cfg(feature = "decimal")]
+        {
+            let expr = parse_expression("1.50m").unwrap();
+            assert_eq!(expr, Expr::Literal(Primitive::Decimal("1.50".parse().unwrap())));
+            assert_eq!(expr.to_source(), "1.50m");
+            assert_eq!(parse_expression(".5m + 3m").unwrap().to_source(), "0.5m + 3m");
+        }
+        #[cfg(not(feature = "decimal"))]
+        assert!(matches!(parse_expression("1.50m"), Err(Error::ParseError(_))));
+        // the suffix must end the literal
+        assert!(parse_expression("1.5mx").is_err());
+    }
+
+    #[test]
+    fn test_identical_expressions_hash_equally() {
+        use std::collections::HashMap;
+        use std::hash::{DefaultHasher, Hash, Hasher};
+
+        fn hash_of(expr: &Expr) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            expr.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let a = parse_expression("user.name.trim() + ' ' + [1, 2.5, true][0] * 3").unwrap();
+        let b = parse_expression("user.name.trim()  +  ' ' + [1,2.5,true][0]*3").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(hash_of(&a), hash_of(&b));
+        assert_ne!(hash_of(&a), hash_of(&parse_expression("user.name.trim() + ' ' + [1, 2.5, true][0] * 4").unwrap()));
+
+        let mut cache = HashMap::new();
+        cache.insert(a, "compiled");
+        assert_eq!(cache.get(&b), Some(&"compiled"));
+        // 0.0 and -0.0 are equal, so they must hash the same too
+        assert_eq!(hash_of(&Expr::Literal(Primitive::Float(0.0))), hash_of(&Expr::Literal(Primitive::Float(-0.0))));
+    }
 }
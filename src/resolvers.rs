@@ -0,0 +1,137 @@
+use crate::evaluator::VariableResolver;
+use crate::types::error::Result;
+use crate::types::value::Value;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+/// Resolves no variables at all, for expressions that need none.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoVars;
+
+impl VariableResolver for NoVars {
+    fn resolve(&self, _name: &str) -> Option<Value> {
+        None
+    }
+}
+
+/// Resolves variables from the process environment. Values are always strings; unset variables (and variables
+/// whose value is not valid Unicode) are unresolved.
+#[derive(Debug, Default, Clone)]
+pub struct EnvResolver {
+    prefix: Option<String>,
+}
+
+impl EnvResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only resolves variables whose name starts with `prefix`, e.g. `APP_`, so expressions cannot read
+    /// unrelated parts of the environment. The name is looked up as written, prefix included.
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self { prefix: Some(prefix.to_string()) }
+    }
+}
+
+impl VariableResolver for EnvResolver {
+    fn resolve(&self, name: &str) -> Option<Value> {
+        if let Some(prefix) = &self.prefix
+            && !name.starts_with(prefix.as_str())
+        {
+            return None;
+        }
+        std::env::var(name).ok().map(Value::from)
+    }
+}
+
+/// Wraps another resolver and records every name the evaluator asks it for, e.g. to know which inputs a cached
+/// result depends on. Names that did not resolve are recorded too, since defining them later changes the
+/// result. Names answered before the resolver is consulted (registered functions) are not seen.
+pub struct TrackingResolver<R: VariableResolver> {
+    inner: R,
+    accessed: RefCell<BTreeSet<String>>,
+}
+
+impl<R: VariableResolver> TrackingResolver<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            accessed: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    /// The names looked up since creation or the last `reset`.
+    pub fn accessed(&self) -> BTreeSet<String> {
+        self.accessed.borrow().clone()
+    }
+
+    pub fn reset(&self) {
+        self.accessed.borrow_mut().clear();
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: VariableResolver> VariableResolver for TrackingResolver<R> {
+    fn resolve(&self, name: &str) -> Option<Value> {
+        self.accessed.borrow_mut().insert(name.to_string());
+        self.inner.resolve(name)
+    }
+
+    fn try_resolve(&self, name: &str) -> Result<Option<Value>> {
+        self.accessed.borrow_mut().insert(name.to_string());
+        self.inner.try_resolve(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    #[test]
+    fn env_resolver() {
+        // only reads variables cargo sets for the test run: changing the environment is unsound while other test
+        // threads may read it
+        let all = EnvResolver::new();
+        let expected = format!("{}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        assert_eq!(evaluate("CARGO_PKG_NAME + '-' + CARGO_PKG_VERSION", &all).unwrap().to_string(), expected);
+        assert!(evaluate("EXPR_TEST_UNSET", &all).is_err());
+
+        let pkg = EnvResolver::with_prefix("CARGO_PKG_");
+        assert_eq!(evaluate("CARGO_PKG_NAME.toUpper()", &pkg).unwrap().to_string(), env!("CARGO_PKG_NAME").to_uppercase());
+        assert!(evaluate("CARGO_MANIFEST_DIR", &pkg).is_err());
+        assert!(evaluate("CARGO_MANIFEST_DIR", &all).is_ok());
+    }
+
+    #[test]
+    fn tracking_resolver() {
+        struct Vars;
+        impl VariableResolver for Vars {
+            fn resolve(&self, name: &str) -> Option<Value> {
+                match name {
+                    "a" => Some(Value::from(1i64)),
+                    "b" => Some(Value::from(2i64)),
+                    "cfg.port" => Some(Value::from(80i64)),
+                    _ => None,
+                }
+            }
+        }
+
+        let tracking = TrackingResolver::new(Vars);
+        assert_eq!(evaluate("a > 0 || b > 0", &tracking).unwrap(), Value::from(true));
+        // short-circuiting means `b` was never needed
+        assert_eq!(tracking.accessed().into_iter().collect::<Vec<_>>(), vec!["a"]);
+
+        tracking.reset();
+        assert!(evaluate("cfg.port + missing", &tracking).is_err());
+        assert_eq!(tracking.accessed().into_iter().collect::<Vec<_>>(), vec!["cfg", "cfg.port", "missing"]);
+
+        // an `@` function looks names up only when its body gets to them
+        tracking.reset();
+        assert_eq!(evaluate("[1, 2].map(@ > 0 || missing)", &tracking).unwrap().to_string(), "[true, true]");
+        assert!(tracking.accessed().is_empty());
+    }
+}
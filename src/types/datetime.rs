@@ -0,0 +1,133 @@
+use crate::types::error::{Error, Result};
+use crate::types::members::{Member, Members};
+use crate::types::object::Object;
+use crate::types::primitive::Primitive;
+use crate::types::value::Value;
+use chrono::{DateTime, Datelike, SecondsFormat, Timelike, Utc};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::rc::Rc;
+
+pub fn new(value: DateTime<Utc>) -> Value {
+    Value::Object(Rc::new(DatetimeObject::new(value)))
+}
+
+/// Parses an ISO-8601 / RFC 3339 timestamp such as `2024-03-01T12:30:00Z`. Offsets are normalised to UTC.
+pub fn parse(s: &str) -> Result<Value> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|dt| new(dt.with_timezone(&Utc)))
+        .map_err(|e| Error::EvaluationFailed(format!("invalid datetime '{}': {}", s, e)))
+}
+
+pub struct DatetimeObject {
+    value: DateTime<Utc>,
+}
+
+impl DatetimeObject {
+    pub fn new(value: DateTime<Utc>) -> DatetimeObject {
+        DatetimeObject { value }
+    }
+
+    pub fn value(&self) -> DateTime<Utc> {
+        self.value
+    }
+}
+
+impl Members for DatetimeObject {
+    const TYPE_NAME: &'static str = "datetime";
+    const MEMBER_NAMES: &'static [&'static str] = &["year", "month", "day", "hour", "minute", "second", "timestamp", "format"];
+
+    fn member(&self, name: &str) -> Option<Member> {
+        let dt = self.value;
+        let member = match name {
+            "year" => Member::Property(Value::from(dt.year() as i64)),
+            "month" => Member::Property(Value::from(dt.month() as i64)),
+            "day" => Member::Property(Value::from(dt.day() as i64)),
+            "hour" => Member::Property(Value::from(dt.hour() as i64)),
+            "minute" => Member::Property(Value::from(dt.minute() as i64)),
+            "second" => Member::Property(Value::from(dt.second() as i64)),
+            "timestamp" => Member::Property(Value::from(dt.timestamp())),
+            "format" => Member::method1(move |arg: &Value| {
+                let Value::Primitive(Primitive::Str(fmt)) = arg else {
+                    return Err(Error::TypeMismatch("format expects a string".into()));
+                };
+                // writing (rather than to_string) reports an invalid format string as an error instead of panicking
+                let mut out = String::new();
+                write!(out, "{}", dt.format(fmt)).map_err(|_| Error::EvaluationFailed(format!("invalid datetime format '{}'", fmt)))?;
+                Ok(Value::from(out))
+            }),
+            _ => return None,
+        };
+        Some(member)
+    }
+}
+
+impl Object for DatetimeObject {
+    fn type_name(&self) -> &'static str {
+        Self::TYPE_NAME
+    }
+
+    fn get_member(&self, name: &str) -> Result<Value> {
+        self.lookup_member(name)
+    }
+
+    fn member_names(&self) -> Vec<&'static str> {
+        Self::MEMBER_NAMES.to_vec()
+    }
+
+    fn as_string(&self) -> Option<String> {
+        Some(self.value.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    fn equals(&self, other: &Value) -> bool {
+        self.compare(other) == Some(Ordering::Equal)
+    }
+
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        if let Value::Object(other_obj) = other
+            && let Some(other_dt) = other_obj.as_any().downcast_ref::<DatetimeObject>()
+        {
+            Some(self.value.cmp(&other_dt.value))
+        } else {
+            None
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::evaluate;
+    use crate::evaluator::tests::eval_to_string as eval;
+    use crate::resolvers::NoVars;
+
+    #[test]
+    fn datetime_members() {
+        assert_eq!(eval("datetime('2024-03-01T12:30:05+02:00')"), "2024-03-01T10:30:05Z");
+        assert_eq!(eval("datetime('2024-03-01T12:30:05Z').year"), "2024");
+        assert_eq!(eval("datetime('2024-03-01T12:30:05Z').month"), "3");
+        assert_eq!(eval("datetime('2024-03-01T12:30:05Z').day"), "1");
+        assert_eq!(eval("datetime('2024-03-01T12:30:05Z').hour * 100 + datetime('2024-03-01T12:30:05Z').minute"), "1230");
+        assert_eq!(eval("datetime('1970-01-01T00:01:00Z').timestamp"), "60");
+        assert_eq!(eval("datetime('2024-03-01T12:30:05Z').format('%Y/%m/%d')"), "2024/03/01");
+        assert!(evaluate("datetime('2024-03-01T12:30:05Z').format('%Q')", &NoVars).is_err());
+        assert!(evaluate("datetime('yesterday')", &NoVars).is_err());
+    }
+
+    #[test]
+    fn datetime_comparison() {
+        assert_eq!(eval("datetime('2024-03-01T00:00:00Z') < datetime('2024-03-02T00:00:00Z')"), "true");
+        assert_eq!(eval("datetime('2024-03-01T00:00:00Z') >= datetime('2024-03-02T00:00:00Z')"), "false");
+        assert_eq!(eval("datetime('2024-03-01T02:00:00+02:00') == datetime('2024-03-01T00:00:00Z')"), "true");
+        assert_eq!(eval("now() > datetime('2024-01-01T00:00:00Z')"), "true");
+        assert!(evaluate("datetime('2024-03-01T00:00:00Z') < 5", &NoVars).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use crate::types::error::{Error, Result};
+use crate::types::expression::BinaryOp;
+use crate::types::primitive::Primitive;
+use crate::types::value::Value;
+use rust_decimal::{Decimal, MathematicalOps};
+use std::cmp::Ordering;
+
+// Ints promote to decimal; floats never do, since that would bring back the rounding decimals exist to avoid.
+fn as_decimal(v: &Value) -> Option<Decimal> {
+    match v {
+        Value::Primitive(Primitive::Decimal(d)) => Some(*d),
+        Value::Primitive(Primitive::Int(i)) => Some(Decimal::from(*i)),
+        _ => None,
+    }
+}
+
+fn is_decimal(v: &Value) -> bool {
+    matches!(v, Value::Primitive(Primitive::Decimal(_)))
+}
+
+/// Arithmetic when at least one operand is a decimal. Returns `None` when neither is, so the caller can fall back
+/// to int/float arithmetic.
+pub(crate) fn arithmetic(op: BinaryOp, l: &Value, r: &Value) -> Option<Result<Value>> {
+    if !is_decimal(l) && !is_decimal(r) {
+        return None;
+    }
+    let (Some(a), Some(b)) = (as_decimal(l), as_decimal(r)) else {
+        return Some(Err(Error::TypeMismatch(format!("cannot mix decimal and {} in arithmetic", if is_decimal(l) { r.type_name() } else { l.type_name() }))));
+    };
+    let result = match op {
+        BinaryOp::Add => a.checked_add(b),
+        BinaryOp::Sub => a.checked_sub(b),
+        BinaryOp::Mul => a.checked_mul(b),
+        BinaryOp::Div | BinaryOp::Mod if b.is_zero() => return Some(Err(Error::DivideByZero)),
+        // the scale of a quotient is arbitrary, so drop trailing zeros
+        BinaryOp::Div => a.checked_div(b).map(|q| q.normalize()),
+        BinaryOp::Mod => a.checked_rem(b),
+        BinaryOp::Pow => match r {
+            Value::Primitive(Primitive::Int(exp)) => a.checked_powi(*exp),
+            _ => return Some(Err(Error::TypeMismatch("decimal exponent must be an int".into()))),
+        },
+        _ => return None,
+    };
+    Some(result.map(Value::from).ok_or_else(|| Error::EvaluationFailed("decimal overflow".into())))
+}
+
+/// Exact ordering when at least one operand is a decimal and the other is a decimal or an int.
+pub(crate) fn compare(l: &Value, r: &Value) -> Option<Ordering> {
+    if !is_decimal(l) && !is_decimal(r) {
+        return None;
+    }
+    Some(as_decimal(l)?.cmp(&as_decimal(r)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::evaluate;
+    use crate::evaluator::tests::eval_to_string as eval;
+    use crate::resolvers::NoVars;
+
+    #[test]
+    fn decimal_arithmetic() {
+        assert_eq!(eval("0.1m + 0.2m"), "0.3");
+        assert_eq!(eval("0.1m + 0.2m == 0.3m"), "true");
+        assert_eq!(eval("1.50m"), "1.50");
+        assert_eq!(eval("1.50m * 3"), "4.50");
+        assert_eq!(eval("10m / 4"), "2.5");
+        assert_eq!(eval("10.5m % 3"), "1.5");
+        assert_eq!(eval("1.1m ^ 2"), "1.21");
+        assert_eq!(eval("-1.5m + 1"), "-0.5");
+        assert_eq!(eval("type(2m)"), "number");
+        assert!(evaluate("1.5m + 0.5", &NoVars).is_err());
+        assert!(evaluate("1.5m / 0", &NoVars).is_err());
+        assert!(evaluate("2m ^ 0.5m", &NoVars).is_err());
+        assert!(evaluate("79228162514264337593543950335m * 2", &NoVars).is_err());
+    }
+
+    #[test]
+    fn decimal_comparison() {
+        assert_eq!(eval("1.5m == 1.50m"), "true");
+        assert_eq!(eval("0.3m > 0.29m"), "true");
+        assert_eq!(eval("2 <= 2.00m"), "true");
+        assert_eq!(eval("1.5m < 2"), "true");
+        // equality agrees with the ordering
+        assert_eq!(eval("2 == 2m"), "true");
+        assert_eq!(eval("2.00m != 2"), "false");
+        assert_eq!(eval("2 == 2.5m"), "false");
+        // exact against ints, but comparing with a float goes through the float value
+        assert_eq!(eval("1.5m < 1.6"), "true");
+        assert_eq!(eval("!0.00m"), "true");
+    }
+}
@@ -1,10 +1,10 @@
 use crate::types::error::{Error, Result};
+use crate::types::list;
+use crate::types::members::{Member, Members};
 use crate::types::object::Object;
 use crate::types::primitive::Primitive;
 use crate::types::value::Value;
-use crate::types::{function, list};
 
-use crate::types::function::method0;
 use std::any::Any;
 use std::collections::BTreeMap;
 use std::rc::Rc;
@@ -13,48 +13,55 @@ pub fn new(map: BTreeMap<String, Value>) -> Value {
     Value::Object(Rc::new(DictObject::new(map)))
 }
 pub fn new_string_dict(map: BTreeMap<String, String>) -> DictObject {
-    DictObject {
-        map: map.into_iter().map(|(k, v)| (k, Value::Primitive(Primitive::Str(v)))).collect(),
-    }
+    DictObject::new(map.into_iter().map(|(k, v)| (k, Value::Primitive(Primitive::Str(v)))).collect())
 }
 pub struct DictObject {
-    map: BTreeMap<String, Value>,
+    map: Rc<BTreeMap<String, Value>>,
 }
 
 impl DictObject {
     pub fn new(map: BTreeMap<String, Value>) -> DictObject {
-        DictObject { map }
+        DictObject { map: Rc::new(map) }
     }
-}
 
-impl Object for DictObject {
-    fn type_name(&self) -> &'static str {
-        "dict"
+    pub fn entries(&self) -> &BTreeMap<String, Value> {
+        &self.map
     }
-    fn get_member(&self, name: &str) -> Result<Value> {
-        match name {
-            "length" => Ok(Value::from(self.map.len() as i64)),
+}
+
+impl Members for DictObject {
+    const TYPE_NAME: &'static str = "dict";
+    const MEMBER_NAMES: &'static [&'static str] = &["length", "isEmpty", "keys", "values", "entries", "contains", "get", "filter", "merge", "set", "remove", "mapValues"];
+
+    fn member(&self, name: &str) -> Option<Member> {
+        let member = match name {
+            "length" => Member::Property(Value::from(self.map.len() as i64)),
+            "isEmpty" => Member::Property(Value::from(self.map.is_empty())),
             "keys" => {
-                let keys: Vec<Value> = self.map.keys().cloned().map(Value::from).collect();
-                Ok(function::method0(move || Ok(list::new(keys.clone()))))
+                let base = Rc::clone(&self.map);
+                Member::method0(move || Ok(list::new(base.keys().cloned().map(Value::from).collect())))
             }
             "values" => {
-                let vals: Vec<Value> = self.map.values().cloned().collect();
-                Ok(method0(move || Ok(list::new(vals.clone()))))
+                let base = Rc::clone(&self.map);
+                Member::method0(move || Ok(list::new(base.values().cloned().collect())))
+            }
+            "entries" => {
+                let base = Rc::clone(&self.map);
+                Member::method0(move || Ok(list::new(base.iter().map(|(k, v)| list::new(vec![Value::from(k.as_str()), v.clone()])).collect())))
             }
             "contains" => {
-                let base = self.map.clone();
-                Ok(function::method1(move |arg: &Value| {
+                let base = Rc::clone(&self.map);
+                Member::method1(move |arg: &Value| {
                     if let Value::Primitive(Primitive::Str(s)) = arg {
                         Ok(Value::from(base.contains_key(s)))
                     } else {
                         Err(Error::TypeMismatch("contains expects a string".into()))
                     }
-                }))
+                })
             }
             "get" => {
-                let base = self.map.clone();
-                Ok(function::new(std::rc::Rc::new(move |args: &[Value]| {
+                let base = Rc::clone(&self.map);
+                Member::method(move |args: &[Value]| {
                     if args.len() != 2 {
                         return Err(Error::EvaluationFailed("expected 2 args".into()));
                     }
@@ -63,13 +70,79 @@ impl Object for DictObject {
                         _ => return Err(Error::TypeMismatch("get expects string key".into())),
                     };
                     if let Some(v) = base.get(&key) { Ok(v.clone()) } else { Ok(args[1].clone()) }
-                })))
+                })
             }
-            _ => Err(Error::UnknownMember {
-                type_name: "dict".into(),
-                member: name.to_string(),
-            }),
-        }
+            "filter" => {
+                let base = Rc::clone(&self.map);
+                Member::method1(move |f: &Value| {
+                    // BTreeMap iteration keeps the callback order deterministic (sorted by key)
+                    let mut out = BTreeMap::new();
+                    for (k, v) in base.iter() {
+                        let keep = f.call(&[Value::from(k.as_str()), v.clone()])?;
+                        if keep.coerce_bool().ok_or(Error::TypeMismatch("filter expects the function to return a bool".into()))? {
+                            out.insert(k.clone(), v.clone());
+                        }
+                    }
+                    Ok(new(out))
+                })
+            }
+            "merge" => {
+                let base = Rc::clone(&self.map);
+                Member::method1(move |arg: &Value| {
+                    let Some(other) = arg.downcast_ref::<DictObject>() else {
+                        return Err(Error::TypeMismatch(format!("merge expects a dict, got {}", arg.type_name())));
+                    };
+                    // keys of the argument win
+                    let mut out = (*base).clone();
+                    out.extend(other.map.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    Ok(new(out))
+                })
+            }
+            "set" => {
+                let base = Rc::clone(&self.map);
+                Member::method(move |args: &[Value]| {
+                    if args.len() != 2 {
+                        return Err(Error::EvaluationFailed("expected 2 args".into()));
+                    }
+                    let Value::Primitive(Primitive::Str(key)) = &args[0] else {
+                        return Err(Error::TypeMismatch("set expects string key".into()));
+                    };
+                    let mut out = (*base).clone();
+                    out.insert(key.clone(), args[1].clone());
+                    Ok(new(out))
+                })
+            }
+            "remove" => {
+                let base = Rc::clone(&self.map);
+                Member::method1(move |arg: &Value| {
+                    let Value::Primitive(Primitive::Str(key)) = arg else {
+                        return Err(Error::TypeMismatch("remove expects string key".into()));
+                    };
+                    let mut out = (*base).clone();
+                    out.remove(key);
+                    Ok(new(out))
+                })
+            }
+            "mapValues" => {
+                let base = Rc::clone(&self.map);
+                Member::method1(move |f: &Value| Ok(new(base.iter().map(|(k, v)| Ok((k.clone(), f.call(std::slice::from_ref(v))?))).collect::<Result<_>>()?)))
+            }
+            _ => return None,
+        };
+        Some(member)
+    }
+}
+
+impl Object for DictObject {
+    fn type_name(&self) -> &'static str {
+        Self::TYPE_NAME
+    }
+    fn get_member(&self, name: &str) -> Result<Value> {
+        self.lookup_member(name)
+    }
+
+    fn member_names(&self) -> Vec<&'static str> {
+        Self::MEMBER_NAMES.to_vec()
     }
 
     fn get_key_value(&self, key: &str) -> Result<Value> {
@@ -1,3 +1,4 @@
+use std::ops::Range;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -22,12 +23,55 @@ pub enum Error {
     NotIndexable(String),
     #[error("no such key: {0}")]
     NoSuchKey(String),
+    // the type has no member of that name; a member that exists but fails reports its own error instead
     #[error("unknown member '{member}' for type {type_name}")]
     UnknownMember { type_name: String, member: String },
+    // `obj.field = value` on a type that does not accept assignments to that member
+    #[error("cannot assign to member '{member}' of type {type_name}")]
+    NotAssignable { type_name: String, member: String },
     #[error("parse error: {0}")]
     ParseError(String),
     #[error("internal parse error: {0}")]
     InternalParserError(String),
+    #[error("result too large: {size} exceeds the limit of {limit}")]
+    ResultTooLarge { size: usize, limit: usize },
+    // integer arithmetic whose result does not fit in an i64; the message shows the operation
+    #[error("integer overflow: {0}")]
+    IntegerOverflow(String),
+    #[error("expression nested too deeply (limit: {0})")]
+    RecursionLimit(usize),
+    #[error("{expr}: {reason}")]
+    InvalidExpression { expr: String, reason: Box<Error> },
+    // `error` is about the part of the source text at the byte range `span`
+    #[error("{error} at {}..{}", .span.start, .span.end)]
+    At { span: Range<usize>, error: Box<Error> },
+}
+
+impl Error {
+    /// Attaches the byte range of the source text the error is about. An error that has one already keeps it.
+    pub fn at(self, span: Range<usize>) -> Error {
+        match self {
+            Error::At { .. } => self,
+            error => Error::At { span, error: Box::new(error) },
+        }
+    }
+
+    /// The byte range of the source text the error is about, for underlining it. Only errors from functions that
+    /// take source text have one: an `Expr` does not remember where it was parsed from.
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            Error::At { span, .. } => Some(span.clone()),
+            _ => None,
+        }
+    }
+
+    /// The error without its source position.
+    pub fn without_span(&self) -> &Error {
+        match self {
+            Error::At { error, .. } => error,
+            error => error,
+        }
+    }
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -1,25 +1,40 @@
+use crate::evaluator::Evaluator;
+use crate::parser;
+use crate::resolvers::NoVars;
 use crate::types::primitive::Primitive;
+use crate::types::visitor::Visitor;
+use std::collections::BTreeSet;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Expr {
     Literal(Primitive),
     Var(String),
     ListLiteral(Vec<Expr>),
     DictLiteral(Vec<(Expr, Expr)>),
+    SetLiteral(Vec<Expr>),
     Member { object: Box<Expr>, field: String },
     Index { object: Box<Expr>, index: Box<Expr> },
     Call { callee: Box<Expr>, args: Vec<Expr> },
     Unary { op: UnaryOp, expr: Box<Expr> },
     Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+    // `a < b <= c`, two or more comparisons (see `BinaryOp::is_comparison`) in a row, each operand compared with
+    // the next; a single comparison is a `Binary`
+    Compare { first: Box<Expr>, rest: Vec<(BinaryOp, Expr)> },
+    // `|x, y| body`
+    Lambda { params: Vec<String>, body: Box<Expr> },
+    // `let name = value in body`
+    Let { name: String, value: Box<Expr>, body: Box<Expr> },
+    // `object.field = value`, which only parses as a whole program
+    Assign { object: Box<Expr>, field: String, value: Box<Expr> },
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UnaryOp {
     Not,
     Neg,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BinaryOp {
     Or,
     And,
@@ -29,6 +44,16 @@ pub enum BinaryOp {
     Le,
     Gt,
     Ge,
+    // membership: key of a dict, element of a list, substring of a string
+    In,
+    // `start..end`, a half-open range of ints
+    Range,
+    // bitwise, on ints only
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
     Add,
     Sub,
     Mul,
@@ -36,3 +61,458 @@ pub enum BinaryOp {
     Mod,
     Pow,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+// Binding strength: binary operators use 1..=12, then unary operators, then postfix chains and primaries, which
+// never need parentheses.
+const UNARY_PRECEDENCE: u8 = 13;
+const POSTFIX_PRECEDENCE: u8 = 14;
+
+impl BinaryOp {
+    /// How tightly the operator binds; a higher value binds tighter (`*` is above `+`). The parser derives its
+    /// operator table from this, so printers and formatters can rely on it matching what parses.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::Ne => 3,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge | BinaryOp::In => 4,
+            // `x in 0..n + 1` tests membership in the range `0..(n + 1)`
+            BinaryOp::Range => 5,
+            // between comparison and arithmetic, so `a & mask == 0` compares the result of `&` and `1 + 1 << 2`
+            // shifts 2; among themselves they follow Rust
+            BinaryOp::BitOr => 6,
+            BinaryOp::BitXor => 7,
+            BinaryOp::BitAnd => 8,
+            BinaryOp::Shl | BinaryOp::Shr => 9,
+            BinaryOp::Add | BinaryOp::Sub => 10,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 11,
+            BinaryOp::Pow => 12,
+        }
+    }
+
+    /// Grouping of a chain of operators with equal precedence: `a - b - c` is `(a - b) - c`, `a ^ b ^ c` is
+    /// `a ^ (b ^ c)`.
+    pub fn associativity(self) -> Associativity {
+        match self {
+            BinaryOp::Pow => Associativity::Right,
+            _ => Associativity::Left,
+        }
+    }
+
+    /// `<`, `<=`, `>` and `>=`, which chain: `a < b <= c` means `a < b && b <= c`. A parenthesized comparison ends
+    /// a chain, so `(a < b) < c` compares a bool.
+    pub fn is_comparison(self) -> bool {
+        matches!(self, BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge)
+    }
+}
+
+impl UnaryOp {
+    /// Prefix operators bind tighter than any binary operator: `-a ^ b` is `(-a) ^ b`.
+    pub fn precedence(self) -> u8 {
+        UNARY_PRECEDENCE
+    }
+}
+
+pub(crate) fn binary_symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Or => "||",
+        BinaryOp::And => "&&",
+        BinaryOp::Eq => "==",
+        BinaryOp::Ne => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::Le => "<=",
+        BinaryOp::Gt => ">",
+        BinaryOp::Ge => ">=",
+        BinaryOp::In => "in",
+        BinaryOp::Range => "..",
+        BinaryOp::BitOr => "|",
+        BinaryOp::BitXor => "~",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::Pow => "^",
+    }
+}
+
+impl Expr {
+    /// True if the expression refers to no variables (including `@` and named functions), so it evaluates to
+    /// the same value under any resolver.
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Expr::Literal(_) => true,
+            Expr::Var(_) => false,
+            Expr::ListLiteral(items) | Expr::SetLiteral(items) => items.iter().all(Expr::is_constant),
+            Expr::DictLiteral(pairs) => pairs.iter().all(|(k, v)| k.is_constant() && v.is_constant()),
+            Expr::Member { object, .. } => object.is_constant(),
+            Expr::Index { object, index } => object.is_constant() && index.is_constant(),
+            Expr::Call { callee, args } => callee.is_constant() && args.iter().all(Expr::is_constant),
+            Expr::Unary { expr, .. } => expr.is_constant(),
+            Expr::Binary { left, right, .. } => left.is_constant() && right.is_constant(),
+            Expr::Compare { first, rest } => first.is_constant() && rest.iter().all(|(_, e)| e.is_constant()),
+            // the parameters and the let name are bound by the expression itself
+            Expr::Lambda { .. } | Expr::Let { .. } => unbound_names(self).is_empty(),
+            // assigning is a side effect, however constant its parts
+            Expr::Assign { .. } => false,
+        }
+    }
+
+    /// Evaluates the constant sub-expressions ahead of time, so `1 + 2 * 3` becomes the literal `7` and
+    /// `x + 2 * 3` becomes `x + 6`. This is `Evaluator::partial_evaluate` with no variables known: calls of named
+    /// functions are never folded, and a sub-expression that fails, such as `1 / 0`, is left as it is for the
+    /// final evaluation to report. Folding uses the default `Evaluator` options, so an expression meant for an
+    /// evaluator with, say, `natural_string_order` should not be folded.
+    pub fn fold_constants(self) -> Expr {
+        Evaluator::new(&NoVars).partial_evaluate(&self)
+    }
+
+    /// The names of all variables (and named functions) the expression refers to. Only the root of a member
+    /// chain counts, so `a.b.length` reports `a`; the `@` of implicit functions is not a variable, and neither
+    /// are the parameters of a lambda or the name of a `let` inside its body.
+    pub fn free_vars(&self) -> BTreeSet<String> {
+        let mut vars = unbound_names(self);
+        vars.remove(parser::CURRENT);
+        vars
+    }
+
+    /// Renders the expression back into source form, adding only the parentheses that operator precedence
+    /// requires. Parsing the result yields an equal `Expr`, although the text may differ from the original
+    /// (whitespace, quotes, redundant parentheses). Non-finite floats have no source form.
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        self.write_source(&mut out);
+        out
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary { op, .. } => op.precedence(),
+            Expr::Compare { .. } => BinaryOp::Lt.precedence(),
+            Expr::Unary { op, .. } => op.precedence(),
+            // the body of a lambda or let takes everything to its right
+            Expr::Lambda { .. } | Expr::Let { .. } | Expr::Assign { .. } => 0,
+            // a negative number prints with a leading '-', which re-parses as a negation
+            Expr::Literal(Primitive::Int(i)) if *i < 0 => UNARY_PRECEDENCE,
+            Expr::Literal(Primitive::Float(f)) if f.is_sign_negative() => UNARY_PRECEDENCE,
+            #[cfg(feature = "decimal")]
+            Expr::Literal(Primitive::Decimal(d)) if d.is_sign_negative() => UNARY_PRECEDENCE,
+            _ => POSTFIX_PRECEDENCE,
+        }
+    }
+
+    fn write_operand(&self, out: &mut String, parenthesize: bool) {
+        if parenthesize {
+            out.push('(');
+            self.write_source(out);
+            out.push(')');
+        } else {
+            self.write_source(out);
+        }
+    }
+
+    fn write_source(&self, out: &mut String) {
+        match self {
+            Expr::Literal(p) => write_literal(p, out),
+            Expr::Var(name) => out.push_str(name),
+            Expr::ListLiteral(items) => {
+                out.push('[');
+                write_list(items, out);
+                out.push(']');
+            }
+            Expr::DictLiteral(pairs) => {
+                out.push('{');
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    key.write_source(out);
+                    out.push_str(": ");
+                    value.write_source(out);
+                }
+                out.push('}');
+            }
+            Expr::SetLiteral(items) => {
+                out.push('{');
+                write_list(items, out);
+                out.push('}');
+            }
+            Expr::Member { object, field } => {
+                object.write_operand(out, object.precedence() < POSTFIX_PRECEDENCE);
+                out.push('.');
+                out.push_str(field);
+            }
+            Expr::Index { object, index } => {
+                object.write_operand(out, object.precedence() < POSTFIX_PRECEDENCE);
+                out.push('[');
+                index.write_source(out);
+                out.push(']');
+            }
+            Expr::Call { callee, args } => {
+                callee.write_operand(out, callee.precedence() < POSTFIX_PRECEDENCE);
+                out.push('(');
+                write_list(args, out);
+                out.push(')');
+            }
+            Expr::Unary { op, expr } => {
+                out.push(match op {
+                    UnaryOp::Not => '!',
+                    UnaryOp::Neg => '-',
+                });
+                expr.write_operand(out, expr.precedence() < UNARY_PRECEDENCE);
+            }
+            Expr::Binary { op, left, right } => {
+                let prec = op.precedence();
+                let right_assoc = op.associativity() == Associativity::Right;
+                let (lp, rp) = (left.precedence(), right.precedence());
+                // a comparison on the left would otherwise print as a longer chain
+                left.write_operand(out, lp < prec || (lp == prec && (right_assoc || op.is_comparison())));
+                out.push(' ');
+                out.push_str(binary_symbol(*op));
+                out.push(' ');
+                right.write_operand(out, rp < prec || (rp == prec && !right_assoc));
+            }
+            Expr::Compare { first, rest } => {
+                let prec = BinaryOp::Lt.precedence();
+                first.write_operand(out, first.precedence() <= prec);
+                for (op, operand) in rest {
+                    out.push(' ');
+                    out.push_str(binary_symbol(*op));
+                    out.push(' ');
+                    operand.write_operand(out, operand.precedence() <= prec);
+                }
+            }
+            Expr::Lambda { params, body } => {
+                out.push('|');
+                out.push_str(&params.join(", "));
+                out.push_str("| ");
+                body.write_source(out);
+            }
+            Expr::Let { name, value, body } => {
+                out.push_str("let ");
+                out.push_str(name);
+                out.push_str(" = ");
+                value.write_operand(out, has_bare_in(value));
+                out.push_str(" in ");
+                body.write_source(out);
+            }
+            Expr::Assign { object, field, value } => {
+                object.write_operand(out, object.precedence() < POSTFIX_PRECEDENCE);
+                out.push('.');
+                out.push_str(field);
+                out.push_str(" = ");
+                value.write_source(out);
+            }
+        }
+    }
+}
+
+// Whether the printed expression could contain an `in` outside of brackets, which would end the value of a `let`
+// early. Conservative: `!(a in b)` is reported although it prints with parentheses.
+fn has_bare_in(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lambda { .. } | Expr::Let { .. } | Expr::Assign { .. } => true,
+        Expr::Binary { op, left, right } => *op == BinaryOp::In || has_bare_in(left) || has_bare_in(right),
+        Expr::Compare { first, rest } => has_bare_in(first) || rest.iter().any(|(_, e)| has_bare_in(e)),
+        Expr::Unary { expr, .. } => has_bare_in(expr),
+        _ => false,
+    }
+}
+
+// Every name the expression looks up, `@` included, that is not a parameter of an enclosing lambda.
+fn unbound_names(expr: &Expr) -> BTreeSet<String> {
+    struct Names {
+        found: BTreeSet<String>,
+        bound: Vec<String>,
+    }
+    impl Visitor for Names {
+        fn visit_var(&mut self, name: &str) {
+            if !self.bound.iter().any(|b| b == name) {
+                self.found.insert(name.to_string());
+            }
+        }
+
+        fn visit_lambda(&mut self, params: &[String], body: &Expr) {
+            let outer = self.bound.len();
+            self.bound.extend(params.iter().cloned());
+            self.visit_expr(body);
+            self.bound.truncate(outer);
+        }
+
+        fn visit_let(&mut self, name: &str, value: &Expr, body: &Expr) {
+            self.visit_expr(value);
+            self.bound.push(name.to_string());
+            self.visit_expr(body);
+            self.bound.pop();
+        }
+    }
+
+    let mut names = Names { found: BTreeSet::new(), bound: Vec::new() };
+    names.visit_expr(expr);
+    names.found
+}
+
+fn write_list(items: &[Expr], out: &mut String) {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        item.write_source(out);
+    }
+}
+
+fn write_literal(p: &Primitive, out: &mut String) {
+    match p {
+        Primitive::Int(i) => out.push_str(&i.to_string()),
+        // Debug keeps the decimal point ("2.0"), so the literal stays a float when re-parsed
+        Primitive::Float(f) => out.push_str(&format!("{:?}", f)),
+        Primitive::Bool(b) => out.push_str(&b.to_string()),
+        Primitive::Null => out.push_str("null"),
+        #[cfg(feature = "decimal")]
+        Primitive::Decimal(d) => {
+            out.push_str(&d.to_string());
+            out.push('m');
+        }
+        Primitive::Str(s) => {
+            out.push('"');
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    '\0' => out.push_str("\\0"),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_expression;
+
+    #[test]
+    fn to_source_round_trips() {
+        let cases = [
+            "1 + 2 * 3",
+            "(1 + 2) * 3",
+            "1 - (2 - 3)",
+            "(1 - 2) - 3",
+            "2 ^ 3 ^ 2",
+            "(2 ^ 3) ^ 2",
+            "-(1 + 2)",
+            "--3",
+            "!a && (b || c)",
+            "a <= b && c >= d",
+            "a < b <= c",
+            "(a < b) < c",
+            "a < (b < c)",
+            "(a < b < c) < d",
+            "x in xs < 1 < y",
+            "(a + b).length",
+            "(-x)[0]",
+            "f(1, [2, 3], {'k': \"v\"})(4)",
+            "obj.items[i + 1].name.toUpper()",
+            "xs.map(@ * 2).filter(@.ok)",
+            "'quote \" and \\\\ and \\n'",
+            "'tab\\t cr\\r nul\\0 \\u00e9'",
+            "1.0 + 2.5e-7 + .5 + 1e300",
+            "a == (b == c)",
+            "'a' in keys && !(x in xs)",
+            "xs.reduce(|acc, x| acc + x, 0)",
+            "(|x| x)(1) + (|| 2)()",
+            "|a| |b| a || b",
+            "let a = 1 in let b = a + 1 in a * b",
+            "let found = (x in xs) in found || (let y = 1 in y) in ys",
+            "let f = (|x| x) in f(1)",
+            "(let a = 1 in a) + 1",
+            "app.config.port = port + 1",
+            "(a || b).flag = |x| x",
+        ];
+        for src in cases {
+            let parsed = parse_expression(src).unwrap();
+            let printed = parsed.to_source();
+            let reparsed = parse_expression(&printed).unwrap_or_else(|e| panic!("'{}' printed as unparsable '{}': {}", src, printed, e));
+            assert_eq!(parsed, reparsed, "'{}' printed as '{}'", src, printed);
+            assert_eq!(printed, reparsed.to_source(), "printing is not a fixed point for '{}'", src);
+        }
+    }
+
+    #[test]
+    fn free_vars() {
+        let vars = |src: &str| parse_expression(src).unwrap().free_vars().into_iter().collect::<Vec<_>>();
+        assert_eq!(vars("a + b.c * f(d, e)"), vec!["a", "b", "d", "e", "f"]);
+        assert_eq!(vars("x.length + x[i] + {k: v}.keys()"), vec!["i", "k", "v", "x"]);
+        assert_eq!(vars("xs.map(@ * factor)"), vec!["factor", "xs"]);
+        assert!(vars("1 + 'a'.length").is_empty());
+        // lambda parameters are bound only inside the lambda
+        assert_eq!(vars("x + xs.map(|x, i| x * i + k)"), vec!["k", "x", "xs"]);
+        assert!(parse_expression("|a| |b| a + b").unwrap().is_constant());
+        assert!(!parse_expression("|a| a + @").unwrap().is_constant());
+        // so is the name of a let, in its body only
+        assert_eq!(vars("let a = a + b in a * c"), vec!["a", "b", "c"]);
+        assert!(parse_expression("let a = 2 in a * a").unwrap().is_constant());
+    }
+
+    #[test]
+    fn operator_precedence_table() {
+        assert!(BinaryOp::Mul.precedence() > BinaryOp::Add.precedence());
+        assert!(BinaryOp::Add.precedence() > BinaryOp::Lt.precedence());
+        assert!(UnaryOp::Neg.precedence() > BinaryOp::Pow.precedence());
+        assert_eq!(BinaryOp::Pow.associativity(), Associativity::Right);
+        assert_eq!(BinaryOp::Sub.associativity(), Associativity::Left);
+        // the parser agrees with the table
+        match parse_expression("1 + 2 * 3").unwrap() {
+            Expr::Binary { op, right, .. } => {
+                assert_eq!(op, BinaryOp::Add);
+                assert!(matches!(*right, Expr::Binary { op: BinaryOp::Mul, .. }));
+            }
+            other => panic!("unexpected parse: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fold_constants() {
+        let fold = |src: &str| parse_expression(src).unwrap().fold_constants().to_source();
+        assert_eq!(parse_expression("1 + 2 * 3").unwrap().fold_constants(), Expr::Literal(Primitive::Int(7)));
+        assert_eq!(fold("x + 2 * 3"), "x + 6");
+        assert_eq!(fold("'ab'.toUpper() + name"), "\"AB\" + name");
+        assert_eq!(fold("[1 + 1, x, {'k': 2 * 4}]"), "[2, x, {\"k\": 8}]");
+        assert_eq!(fold("[3, 1, 2].map(|n| n * 2).filter(|n| n > 2)"), "[6, 4]");
+        // an `@` argument is not constant, as it refers to `@`
+        assert_eq!(fold("[3, 1, 2].map(|n| n * 2).filter(@ > 2)"), "[6, 2, 4].filter(@ > 2)");
+        assert_eq!(fold("f(1 + 1) && (|n| n + 1 * 2)(x)"), "f(2) && (|n| n + 1 * 2)(x)");
+        // failures are left for runtime, and so are named functions, which could be anything, and values without a
+        // literal form
+        for src in ["1 / 0 + x", "[1][5]", "rand() + 1", "1 .. 3", "\"ab\" * 9223372036854775807"] {
+            assert_eq!(fold(src), src);
+        }
+    }
+
+    #[test]
+    fn to_source_uses_minimal_parentheses() {
+        assert_eq!(parse_expression("((1 + (2 * 3)))").unwrap().to_source(), "1 + 2 * 3");
+        assert_eq!(parse_expression("(1+2)*3").unwrap().to_source(), "(1 + 2) * 3");
+        assert_eq!(parse_expression("2^(3^2)").unwrap().to_source(), "2 ^ 3 ^ 2");
+        assert_eq!(parse_expression("(a.b)(c)").unwrap().to_source(), "a.b(c)");
+        let folded = Expr::Member {
+            object: Box::new(Expr::Literal(Primitive::Int(-2))),
+            field: "x".into(),
+        };
+        assert_eq!(folded.to_source(), "(-2).x");
+    }
+}
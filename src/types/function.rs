@@ -25,6 +25,14 @@ impl Object for Function {
         "function"
     }
 
+    fn get_index(&self, _index: i64) -> Result<Value> {
+        Err(Error::NotIndexable(self.type_name().into()))
+    }
+
+    fn get_key_value(&self, _key: &str) -> Result<Value> {
+        Err(Error::NotIndexable(self.type_name().into()))
+    }
+
     fn call(&self, args: &[Value]) -> Result<Value> {
         self.callable.as_ref()(args)
     }
@@ -38,26 +46,40 @@ impl Object for Function {
     }
 }
 
-pub fn method0<F>(f: F) -> Value
+pub fn callable0<F>(f: F) -> Callable
 where
     F: Fn() -> Result<Value> + 'static,
 {
-    new(Rc::new(move |args: &[Value]| {
+    Rc::new(move |args: &[Value]| {
         if !args.is_empty() {
             return Err(Error::EvaluationFailed("expected 0 args".into()));
         }
         f()
-    }))
+    })
 }
 
-pub fn method1<F>(f: F) -> Value
+pub fn callable1<F>(f: F) -> Callable
 where
     F: Fn(&Value) -> Result<Value> + 'static,
 {
-    new(Rc::new(move |args: &[Value]| {
+    Rc::new(move |args: &[Value]| {
         if args.len() != 1 {
             return Err(Error::EvaluationFailed("expected 1 arg".into()));
         }
         f(&args[0])
-    }))
+    })
+}
+
+pub fn method0<F>(f: F) -> Value
+where
+    F: Fn() -> Result<Value> + 'static,
+{
+    new(callable0(f))
+}
+
+pub fn method1<F>(f: F) -> Value
+where
+    F: Fn(&Value) -> Result<Value> + 'static,
+{
+    new(callable1(f))
 }
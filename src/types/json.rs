@@ -0,0 +1,110 @@
+use crate::types::dict::{self, DictObject};
+use crate::types::error::{Error, Result};
+use crate::types::primitive::Primitive;
+use crate::types::value::Value;
+use crate::types::{list, set};
+use serde_json::{Map, Number};
+
+// JSON numbers become ints when they fit in an i64 and floats otherwise; objects become dicts and arrays lists.
+impl From<serde_json::Value> for Value {
+    fn from(json: serde_json::Value) -> Self {
+        match json {
+            serde_json::Value::Null => Value::Primitive(Primitive::Null),
+            serde_json::Value::Bool(b) => Value::from(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::from(i),
+                None => Value::from(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(s) => Value::from(s),
+            serde_json::Value::Array(items) => list::new(items.into_iter().map(Value::from).collect()),
+            serde_json::Value::Object(map) => dict::new(map.into_iter().map(|(k, v)| (k, Value::from(v))).collect()),
+        }
+    }
+}
+
+// Functions, custom objects and non-finite floats have no JSON form. Decimals become strings so that no precision
+// is lost.
+impl TryFrom<&Value> for serde_json::Value {
+    type Error = Error;
+    fn try_from(v: &Value) -> Result<Self> {
+        let json = match v {
+            Value::Primitive(Primitive::Null) => serde_json::Value::Null,
+            Value::Primitive(Primitive::Bool(b)) => serde_json::Value::Bool(*b),
+            Value::Primitive(Primitive::Int(i)) => serde_json::Value::Number(Number::from(*i)),
+            Value::Primitive(Primitive::Float(f)) => match Number::from_f64(*f) {
+                Some(n) => serde_json::Value::Number(n),
+                None => return Err(Error::TypeMismatch(format!("{} has no JSON representation", f))),
+            },
+            Value::Primitive(Primitive::Str(s)) => serde_json::Value::String(s.clone()),
+            #[cfg(feature = "decimal")]
+            Value::Primitive(Primitive::Decimal(d)) => serde_json::Value::String(d.to_string()),
+            Value::Object(_) => {
+                if let Some(l) = list::as_list(v) {
+                    serde_json::Value::Array(l.items().iter().map(serde_json::Value::try_from).collect::<Result<_>>()?)
+                } else if let Some(s) = set::as_set(v) {
+                    serde_json::Value::Array(s.items().iter().map(|p| serde_json::Value::try_from(&Value::Primitive(p.clone()))).collect::<Result<_>>()?)
+                } else if let Some(d) = v.downcast_ref::<DictObject>() {
+                    let map = d.entries().iter().map(|(k, v)| Ok((k.clone(), serde_json::Value::try_from(v)?))).collect::<Result<Map<_, _>>>()?;
+                    serde_json::Value::Object(map)
+                } else {
+                    return Err(Error::TypeMismatch(format!("cannot convert {} to JSON", v.type_name())));
+                }
+            }
+        };
+        Ok(json)
+    }
+}
+
+impl TryFrom<Value> for serde_json::Value {
+    type Error = Error;
+    fn try_from(v: Value) -> Result<Self> {
+        serde_json::Value::try_from(&v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::{VariableResolver, evaluate};
+    use crate::types::function;
+    use serde_json::json;
+    use std::rc::Rc;
+
+    struct Doc(Value);
+    impl VariableResolver for Doc {
+        fn resolve(&self, name: &str) -> Option<Value> {
+            (name == "doc").then(|| self.0.clone())
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let original = json!({
+            "name": "widget",
+            "count": 3,
+            "price": 2.5,
+            "big": 18446744073709551615u64,
+            "tags": ["a", "b"],
+            "nested": {"ok": true, "missing": null, "empty": []},
+        });
+        let value = Value::from(original.clone());
+        let doc = Doc(value.clone());
+        assert_eq!(evaluate("doc['nested']['ok'] && doc['tags'][1] == 'b'", &doc).unwrap(), Value::from(true));
+        assert_eq!(evaluate("doc['count'] * 2", &doc).unwrap(), Value::from(6i64));
+
+        let mut expected = original;
+        // u64 values beyond i64 come back as floats
+        expected["big"] = json!(18446744073709551615u64 as f64);
+        // and so does the JSON text
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&value.to_json_string().unwrap()).unwrap(), expected);
+        assert_eq!(serde_json::Value::try_from(value).unwrap(), expected);
+    }
+
+    #[test]
+    fn unconvertible_values() {
+        let f = function::new(Rc::new(|_: &[Value]| Ok(Value::from(1i64))));
+        assert!(matches!(serde_json::Value::try_from(&f), Err(Error::TypeMismatch(_))));
+        assert!(serde_json::Value::try_from(list::new(vec![Value::from(1i64), f])).is_err());
+        assert!(serde_json::Value::try_from(Value::from(f64::NAN)).is_err());
+    }
+}
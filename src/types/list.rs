@@ -1,40 +1,54 @@
+use crate::types::dict;
 use crate::types::error::{Error, Result};
-use crate::types::function;
+use crate::types::members::{Member, Members, slice_bounds};
 use crate::types::object::Object;
 use crate::types::primitive::Primitive;
 use crate::types::value::Value;
 use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::rc::Rc;
 
 pub fn new(items: Vec<Value>) -> Value {
     Value::Object(Rc::new(ListObject::new(items)))
 }
 
+/// Returns the list behind `value`, if it is one.
+pub fn as_list(value: &Value) -> Option<&ListObject> {
+    value.downcast_ref::<ListObject>()
+}
+
 pub struct ListObject {
-    list: Vec<Value>,
+    list: Rc<Vec<Value>>,
 }
 
 impl ListObject {
     pub fn new(list: Vec<Value>) -> ListObject {
-        ListObject { list }
+        ListObject { list: Rc::new(list) }
     }
-}
 
-impl Object for ListObject {
-    fn type_name(&self) -> &'static str {
-        "list"
+    pub fn items(&self) -> &[Value] {
+        &self.list
     }
+}
 
-    fn get_member(&self, name: &str) -> Result<Value> {
-        match name {
-            "length" => Ok(Value::from(self.list.len() as i64)),
+impl Members for ListObject {
+    const TYPE_NAME: &'static str = "list";
+    const MEMBER_NAMES: &'static [&'static str] = &[
+        "length", "isEmpty", "contains", "get", "join", "map", "flatMap", "chunk", "groupBy", "filter", "reduce", "slice", "sum", "min", "max", "avg", "reverse", "first", "last", "indexOf", "distinct",
+    ];
+
+    fn member(&self, name: &str) -> Option<Member> {
+        let member = match name {
+            "length" => Member::Property(Value::from(self.list.len() as i64)),
+            "isEmpty" => Member::Property(Value::from(self.list.is_empty())),
             "contains" => {
-                let base = self.list.clone();
-                Ok(function::method1(move |arg: &Value| Ok(Value::from(base.iter().any(|v| v == arg)))))
+                let base = Rc::clone(&self.list);
+                Member::method1(move |arg: &Value| Ok(Value::from(base.iter().any(|v| v == arg))))
             }
             "get" => {
-                let base = self.list.clone();
-                Ok(function::new(Rc::new(move |args: &[Value]| {
+                let base = Rc::clone(&self.list);
+                Member::method(move |args: &[Value]| {
                     if args.len() != 2 {
                         return Err(Error::EvaluationFailed("expected 2 args".into()));
                     }
@@ -48,11 +62,11 @@ impl Object for ListObject {
                         return Ok(args[1].clone());
                     }
                     Ok(base[eff as usize].clone())
-                })))
+                })
             }
             "join" => {
-                let base = self.list.clone();
-                Ok(function::method1(move |arg: &Value| {
+                let base = Rc::clone(&self.list);
+                Member::method1(move |arg: &Value| {
                     let joiner = if let Value::Primitive(Primitive::Str(s)) = arg {
                         s.clone()
                     } else {
@@ -60,14 +74,193 @@ impl Object for ListObject {
                     };
                     let parts: Vec<String> = base.iter().map(|v| v.as_str_lossy()).collect();
                     Ok(Value::from(parts.join(&joiner)))
-                }))
+                })
+            }
+            "map" => {
+                let base = Rc::clone(&self.list);
+                Member::method1(move |f: &Value| Ok(new(base.iter().map(|v| f.call(std::slice::from_ref(v))).collect::<Result<_>>()?)))
+            }
+            "flatMap" => {
+                let base = Rc::clone(&self.list);
+                Member::method1(move |f: &Value| {
+                    let mut out = Vec::new();
+                    for v in base.iter() {
+                        // the callback must return a list; its elements are spliced into the result
+                        let mapped = f.call(std::slice::from_ref(v))?;
+                        let Some(list) = as_list(&mapped) else {
+                            return Err(Error::TypeMismatch(format!("flatMap expects the function to return a list, got {}", mapped.type_name())));
+                        };
+                        out.extend(list.items().iter().cloned());
+                    }
+                    Ok(new(out))
+                })
+            }
+            "chunk" => {
+                let base = Rc::clone(&self.list);
+                Member::method1(move |arg: &Value| {
+                    let size = match arg {
+                        Value::Primitive(Primitive::Int(n)) if *n > 0 => *n as usize,
+                        Value::Primitive(Primitive::Int(n)) => return Err(Error::EvaluationFailed(format!("chunk size must be positive, got {}", n))),
+                        _ => return Err(Error::TypeMismatch("chunk expects an int size".into())),
+                    };
+                    Ok(new(base.chunks(size).map(|c| new(c.to_vec())).collect()))
+                })
+            }
+            "groupBy" => {
+                let base = Rc::clone(&self.list);
+                Member::method1(move |f: &Value| {
+                    // keys are stringified; elements keep their input order within each group
+                    let mut groups: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+                    for v in base.iter() {
+                        let key = f.call(std::slice::from_ref(v))?.as_str_lossy();
+                        groups.entry(key).or_default().push(v.clone());
+                    }
+                    Ok(dict::new(groups.into_iter().map(|(k, items)| (k, new(items))).collect()))
+                })
+            }
+            "filter" => {
+                let base = Rc::clone(&self.list);
+                Member::method1(move |f: &Value| {
+                    let mut out = Vec::new();
+                    for v in base.iter() {
+                        let keep = f.call(std::slice::from_ref(v))?;
+                        if keep.coerce_bool().ok_or(Error::TypeMismatch("filter expects the function to return a bool".into()))? {
+                            out.push(v.clone());
+                        }
+                    }
+                    Ok(new(out))
+                })
+            }
+            "reduce" => {
+                let base = Rc::clone(&self.list);
+                Member::method(move |args: &[Value]| {
+                    let [f, initial] = args else {
+                        return Err(Error::EvaluationFailed("expected 2 args".into()));
+                    };
+                    // folds from the left: f(f(initial, xs[0]), xs[1]) ...
+                    base.iter().try_fold(initial.clone(), |acc, v| f.call(&[acc, v.clone()]))
+                })
+            }
+            "slice" => {
+                let base = Rc::clone(&self.list);
+                Member::method(move |args: &[Value]| {
+                    let (start, end) = slice_bounds(base.len(), args, "slice")?;
+                    Ok(new(base[start..end].to_vec()))
+                })
+            }
+            "sum" => {
+                let base = Rc::clone(&self.list);
+                Member::method0(move || sum(&base, "sum").map(|(total, _)| total))
+            }
+            "avg" => {
+                let base = Rc::clone(&self.list);
+                Member::method0(move || {
+                    let (total, count) = sum(&base, "avg")?;
+                    Ok(Value::from(total.to_float_lossy().unwrap_or(f64::NAN) / count as f64))
+                })
+            }
+            "min" => {
+                let base = Rc::clone(&self.list);
+                Member::method0(move || extreme(&base, "min", Ordering::Less))
+            }
+            "max" => {
+                let base = Rc::clone(&self.list);
+                Member::method0(move || extreme(&base, "max", Ordering::Greater))
             }
-            _ => Err(Error::UnknownMember {
-                type_name: "list".into(),
-                member: name.to_string(),
-            }),
+            "reverse" => {
+                let base = Rc::clone(&self.list);
+                Member::method0(move || Ok(new(base.iter().rev().cloned().collect())))
+            }
+            "first" => {
+                let base = Rc::clone(&self.list);
+                Member::method0(move || base.first().cloned().ok_or(Error::IndexOutOfBounds { index: 0, len: 0 }))
+            }
+            "last" => {
+                let base = Rc::clone(&self.list);
+                Member::method0(move || base.last().cloned().ok_or(Error::IndexOutOfBounds { index: 0, len: 0 }))
+            }
+            "indexOf" => {
+                let base = Rc::clone(&self.list);
+                Member::method1(move |arg: &Value| Ok(Value::from(base.iter().position(|v| v == arg).map_or(-1, |i| i as i64))))
+            }
+            "distinct" => {
+                let base = Rc::clone(&self.list);
+                Member::method0(move || {
+                    // values are not hashable, so this compares against everything kept so far
+                    let mut out: Vec<Value> = Vec::new();
+                    for v in base.iter() {
+                        if !out.contains(v) {
+                            out.push(v.clone());
+                        }
+                    }
+                    Ok(new(out))
+                })
+            }
+            _ => return None,
+        };
+        Some(member)
+    }
+}
+
+// Sum of a list of numbers and its length. Stays an int while every element is one (overflow is an error),
+// like `+`; any float makes the result a float.
+fn sum(items: &[Value], method: &str) -> Result<(Value, usize)> {
+    if items.is_empty() {
+        return Err(Error::EvaluationFailed(format!("{} of an empty list", method)));
+    }
+    let mut int_total: Option<i64> = Some(0);
+    let mut float_total = 0.0;
+    for v in items {
+        let Some(f) = v.to_float_lossy() else {
+            return Err(Error::TypeMismatch(format!("{} expects a list of numbers, found {}", method, v.type_name())));
+        };
+        float_total += f;
+        int_total = match (int_total, v) {
+            (Some(total), Value::Primitive(Primitive::Int(i))) => Some(total.checked_add(*i).ok_or_else(|| Error::IntegerOverflow(format!("{} of the list", method)))?),
+            _ => None,
+        };
+    }
+    Ok((int_total.map_or(Value::from(float_total), Value::from), items.len()))
+}
+
+// The smallest (`wanted` = Less) or largest element of a list of numbers or a list of strings. The first of several
+// equal elements wins.
+fn extreme(items: &[Value], method: &str, wanted: Ordering) -> Result<Value> {
+    let Some(first) = items.first() else {
+        return Err(Error::EvaluationFailed(format!("{} of an empty list", method)));
+    };
+    let mismatch = || Error::TypeMismatch(format!("{} expects a list of numbers or a list of strings", method));
+    let mut best = first;
+    for v in &items[1..] {
+        let ord = match (v, best) {
+            (Value::Primitive(Primitive::Str(a)), Value::Primitive(Primitive::Str(b))) => a.cmp(b),
+            _ => match (v.to_float_lossy(), best.to_float_lossy()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                _ => return Err(mismatch()),
+            },
+        };
+        if ord == wanted {
+            best = v;
         }
     }
+    if best.to_float_lossy().is_none() && !matches!(best, Value::Primitive(Primitive::Str(_))) {
+        return Err(mismatch());
+    }
+    Ok(best.clone())
+}
+
+impl Object for ListObject {
+    fn type_name(&self) -> &'static str {
+        Self::TYPE_NAME
+    }
+
+    fn get_member(&self, name: &str) -> Result<Value> {
+        self.lookup_member(name)
+    }
+
+    fn member_names(&self) -> Vec<&'static str> {
+        Self::MEMBER_NAMES.to_vec()
+    }
 
     fn get_index(&self, index: i64) -> Result<Value> {
         let len = self.list.len() as i64;
@@ -90,7 +283,7 @@ impl Object for ListObject {
         if let Value::Object(other_obj) = other
             && let Some(other_list) = other_obj.as_any().downcast_ref::<ListObject>()
         {
-            self.list == *other_list.list
+            self.list == other_list.list
         } else {
             false
         }
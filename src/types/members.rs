@@ -0,0 +1,306 @@
+use crate::types::dict::DictObject;
+use crate::types::error::{Error, Result};
+use crate::types::function::{self, Callable};
+use crate::types::list::ListObject;
+use crate::types::primitive::Primitive;
+use crate::types::range::RangeObject;
+use crate::types::set::SetObject;
+use crate::types::value::Value;
+use std::rc::Rc;
+
+pub enum Member {
+    Property(Value),
+    Method(Callable),
+}
+
+impl Member {
+    pub fn method<F>(f: F) -> Member
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        Member::Method(Rc::new(f))
+    }
+
+    pub fn method0<F>(f: F) -> Member
+    where
+        F: Fn() -> Result<Value> + 'static,
+    {
+        Member::Method(function::callable0(f))
+    }
+
+    pub fn method1<F>(f: F) -> Member
+    where
+        F: Fn(&Value) -> Result<Value> + 'static,
+    {
+        Member::Method(function::callable1(f))
+    }
+
+    pub fn into_value(self) -> Value {
+        match self {
+            Member::Property(v) => v,
+            Member::Method(callable) => function::new(callable),
+        }
+    }
+}
+
+// Member lookup shared by all built-in types (strings, lists, dicts)
+pub trait Members {
+    const TYPE_NAME: &'static str;
+    // every name `member` resolves, for autocompletion
+    const MEMBER_NAMES: &'static [&'static str];
+
+    fn member(&self, name: &str) -> Option<Member>;
+
+    fn lookup_member(&self, name: &str) -> Result<Value> {
+        self.member(name).map(Member::into_value).ok_or_else(|| Error::UnknownMember {
+            type_name: Self::TYPE_NAME.into(),
+            member: name.to_string(),
+        })
+    }
+}
+
+/// A declarative member table for custom objects. Build it once (typically when the object is created) and
+/// return it from `Object::member_table`; the default `Object::get_member` and `Object::member_names` then
+/// dispatch through it. Like the built-in members, methods capture whatever state they need.
+#[derive(Default)]
+pub struct MemberTable {
+    members: Vec<(&'static str, Member)>,
+}
+
+impl MemberTable {
+    pub fn new() -> MemberTable {
+        MemberTable::default()
+    }
+
+    /// Adds a property; a later entry with the same name replaces an earlier one.
+    pub fn property(self, name: &'static str, value: Value) -> MemberTable {
+        self.with(name, Member::Property(value))
+    }
+
+    pub fn method<F>(self, name: &'static str, f: F) -> MemberTable
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        self.with(name, Member::method(f))
+    }
+
+    pub fn method0<F>(self, name: &'static str, f: F) -> MemberTable
+    where
+        F: Fn() -> Result<Value> + 'static,
+    {
+        self.with(name, Member::method0(f))
+    }
+
+    pub fn method1<F>(self, name: &'static str, f: F) -> MemberTable
+    where
+        F: Fn(&Value) -> Result<Value> + 'static,
+    {
+        self.with(name, Member::method1(f))
+    }
+
+    fn with(mut self, name: &'static str, member: Member) -> MemberTable {
+        self.members.retain(|(n, _)| *n != name);
+        self.members.push((name, member));
+        self
+    }
+
+    /// Looks up `name`, reporting a missing member as `Error::UnknownMember` for `type_name`.
+    pub fn lookup(&self, type_name: &str, name: &str) -> Result<Value> {
+        let member = self.members.iter().find(|(n, _)| *n == name).map(|(_, m)| m);
+        match member {
+            Some(Member::Property(v)) => Ok(v.clone()),
+            Some(Member::Method(callable)) => Ok(function::new(Rc::clone(callable))),
+            None => Err(Error::UnknownMember {
+                type_name: type_name.into(),
+                member: name.to_string(),
+            }),
+        }
+    }
+
+    /// Member names in the order they were added.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.members.iter().map(|(n, _)| *n).collect()
+    }
+}
+
+/// Lists the members available on the built-in type called `type_name` (as reported by `Value::type_name`).
+/// Unknown type names have no members.
+pub fn member_names(type_name: &str) -> &'static [&'static str] {
+    match type_name {
+        <str as Members>::TYPE_NAME => <str as Members>::MEMBER_NAMES,
+        <i64 as Members>::TYPE_NAME => <i64 as Members>::MEMBER_NAMES,
+        ListObject::TYPE_NAME => ListObject::MEMBER_NAMES,
+        DictObject::TYPE_NAME => DictObject::MEMBER_NAMES,
+        SetObject::TYPE_NAME => SetObject::MEMBER_NAMES,
+        RangeObject::TYPE_NAME => RangeObject::MEMBER_NAMES,
+        #[cfg(feature = "chrono")]
+        crate::types::datetime::DatetimeObject::TYPE_NAME => crate::types::datetime::DatetimeObject::MEMBER_NAMES,
+        _ => &[],
+    }
+}
+
+// Resolves `(start[, end])` slice arguments against a sequence of length `len`. Negative indices count from the
+// end, out-of-range indices are clamped and `start > end` yields an empty range.
+pub(crate) fn slice_bounds(len: usize, args: &[Value], method: &str) -> Result<(usize, usize)> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(Error::EvaluationFailed("expected 1 or 2 args".into()));
+    }
+    let len = len as i64;
+    let clamp = |i: i64| (if i < 0 { len + i } else { i }).clamp(0, len);
+    let start = match &args[0] {
+        Value::Primitive(Primitive::Int(i)) => clamp(*i),
+        _ => return Err(Error::TypeMismatch(format!("{} expects int start", method))),
+    };
+    let end = match args.get(1) {
+        None => len,
+        Some(Value::Primitive(Primitive::Int(i))) => clamp(*i),
+        Some(_) => return Err(Error::TypeMismatch(format!("{} expects int end", method))),
+    };
+    Ok((start as usize, end.max(start) as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::range;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    // `member` cannot be enumerated, so a member that resolves without being listed is looked for among the names
+    // any type lists and some that a type might plausibly add
+    fn candidate_names() -> BTreeSet<&'static str> {
+        let listed = [
+            <str as Members>::MEMBER_NAMES,
+            i64::MEMBER_NAMES,
+            f64::MEMBER_NAMES,
+            ListObject::MEMBER_NAMES,
+            DictObject::MEMBER_NAMES,
+            SetObject::MEMBER_NAMES,
+            RangeObject::MEMBER_NAMES,
+        ];
+        let plausible = [
+            "toInt",
+            "toFloat",
+            "toString",
+            "size",
+            "count",
+            "has",
+            "at",
+            "replace",
+            "trimStart",
+            "trimEnd",
+            "sort",
+            "sortBy",
+            "any",
+            "all",
+            "round",
+            "floor",
+            "ceil",
+        ];
+        listed.into_iter().flatten().copied().chain(plausible).collect()
+    }
+
+    fn assert_names_match<M: Members + ?Sized>(members: &M) {
+        for name in M::MEMBER_NAMES {
+            assert!(members.member(name).is_some(), "{} lists member '{}' but does not resolve it", M::TYPE_NAME, name);
+        }
+        for name in candidate_names() {
+            assert!(members.member(name).is_none() || M::MEMBER_NAMES.contains(&name), "{} resolves member '{}' but does not list it", M::TYPE_NAME, name);
+        }
+    }
+
+    #[test]
+    fn member_names_resolve() {
+        assert_names_match("abc");
+        assert_names_match(&1i64);
+        assert_names_match(&1.5f64);
+        assert_names_match(&ListObject::new(vec![Value::from(1i64)]));
+        assert_names_match(&DictObject::new(BTreeMap::new()));
+        assert_names_match(&SetObject::new(Vec::new()));
+        let range = range::new(0, 3).unwrap();
+        assert_names_match(range::as_range(&range).unwrap());
+    }
+
+    // a member that exists but fails (wrong arity, wrong argument type) must not look like a missing one
+    fn assert_errors_are_not_unknown(value: Value) {
+        let bad_args = [vec![], vec![Value::from(true)], vec![Value::from(true), Value::from(true), Value::from(true)]];
+        for name in value.member_names() {
+            let member = value.get_member(name).unwrap();
+            if !matches!(member.type_name(), "function") {
+                continue;
+            }
+            for args in &bad_args {
+                if let Err(e @ Error::UnknownMember { .. }) = member.call(args) {
+                    panic!("{}.{} reported {:?}", value.type_name(), name, e);
+                }
+            }
+        }
+        assert!(matches!(value.get_member("noSuchMember"), Err(Error::UnknownMember { .. })));
+    }
+
+    #[test]
+    fn failing_members_are_not_unknown() {
+        assert_errors_are_not_unknown(Value::from("abc"));
+        assert_errors_are_not_unknown(Value::from(1i64));
+        assert_errors_are_not_unknown(Value::from(1.5));
+        assert_errors_are_not_unknown(Value::Object(Rc::new(ListObject::new(vec![Value::from(1i64)]))));
+        assert_errors_are_not_unknown(Value::Object(Rc::new(DictObject::new(BTreeMap::new()))));
+        assert!(matches!(function::new(Rc::new(|_: &[Value]| Ok(Value::from(1i64)))).get_member("x"), Err(Error::UnknownMember { .. })));
+    }
+
+    #[test]
+    fn member_table_object() {
+        use crate::types::object::Object;
+        use std::any::Any;
+
+        struct Person {
+            members: MemberTable,
+        }
+
+        impl Person {
+            fn new(name: &str) -> Person {
+                let greeter = name.to_string();
+                let members = MemberTable::new()
+                    .property("name", Value::from(name))
+                    .method1("greet", move |other: &Value| Ok(Value::from(format!("{} greets {}", greeter, other))))
+                    .method0("shout", || Ok(Value::from("HEY")));
+                Person { members }
+            }
+        }
+
+        impl Object for Person {
+            fn type_name(&self) -> &'static str {
+                "person"
+            }
+            fn member_table(&self) -> Option<&MemberTable> {
+                Some(&self.members)
+            }
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+
+        let person = Value::Object(Rc::new(Person::new("ann")));
+        assert_eq!(person.get_member("name").unwrap(), Value::from("ann"));
+        assert_eq!(person.get_member("greet").unwrap().call(&[Value::from("bob")]).unwrap(), Value::from("ann greets bob"));
+        assert_eq!(person.get_member("shout").unwrap().call(&[]).unwrap(), Value::from("HEY"));
+        assert_eq!(person.member_names(), vec!["name", "greet", "shout"]);
+        match person.get_member("age") {
+            Err(Error::UnknownMember { type_name, member }) => assert_eq!((type_name.as_str(), member.as_str()), ("person", "age")),
+            other => panic!("expected UnknownMember, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn member_names_by_value_and_type() {
+        assert_eq!(Value::from("abc").member_names(), <str as Members>::MEMBER_NAMES.to_vec());
+        assert_eq!(member_names("string"), <str as Members>::MEMBER_NAMES);
+        assert!(member_names("list").contains(&"join"));
+        assert!(member_names("dict").contains(&"keys"));
+        assert!(member_names("number").contains(&"isNaN"));
+        assert_eq!(Value::from(1.5).member_names(), Value::from(1i64).member_names());
+        assert!(Value::from(true).member_names().is_empty());
+    }
+}
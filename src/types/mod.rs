@@ -1,9 +1,21 @@
+#[cfg(feature = "chrono")]
+pub mod datetime;
+#[cfg(feature = "decimal")]
+pub(crate) mod decimal;
 pub mod dict;
 pub mod error;
 pub mod expression;
 pub mod function;
+#[cfg(feature = "serde")]
+pub(crate) mod json;
 pub mod list;
+pub mod members;
+pub(crate) mod number_members;
 pub mod object;
 pub mod primitive;
-mod string_members;
+pub mod range;
+pub mod set;
+pub(crate) mod string_members;
+pub mod token;
 pub mod value;
+pub mod visitor;
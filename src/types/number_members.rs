@@ -0,0 +1,46 @@
+use crate::types::error::Error;
+use crate::types::members::{Member, Members};
+use crate::types::value::Value;
+
+// Ints and floats share the "number" type, so both expose the same member names.
+const NUMBER_MEMBER_NAMES: &[&str] = &["abs", "sign", "isNaN", "isInfinite"];
+
+impl Members for i64 {
+    const TYPE_NAME: &'static str = "number";
+    const MEMBER_NAMES: &'static [&'static str] = NUMBER_MEMBER_NAMES;
+
+    fn member(&self, name: &str) -> Option<Member> {
+        let n = *self;
+        let member = match name {
+            "abs" => Member::method0(move || n.checked_abs().map(Value::from).ok_or_else(|| Error::IntegerOverflow(format!("abs of {}", n)))),
+            "sign" => Member::Property(Value::from(n.signum())),
+            "isNaN" | "isInfinite" => Member::Property(Value::from(false)),
+            _ => return None,
+        };
+        Some(member)
+    }
+}
+
+impl Members for f64 {
+    const TYPE_NAME: &'static str = "number";
+    const MEMBER_NAMES: &'static [&'static str] = NUMBER_MEMBER_NAMES;
+
+    fn member(&self, name: &str) -> Option<Member> {
+        let n = *self;
+        let member = match name {
+            "abs" => Member::method0(move || Ok(Value::from(n.abs()))),
+            // -1, 0 or 1 as an int; NaN has no sign and stays NaN
+            "sign" => Member::Property(if n.is_nan() {
+                Value::from(f64::NAN)
+            } else if n == 0.0 {
+                Value::from(0i64)
+            } else {
+                Value::from(n.signum() as i64)
+            }),
+            "isNaN" => Member::Property(Value::from(n.is_nan())),
+            "isInfinite" => Member::Property(Value::from(n.is_infinite())),
+            _ => return None,
+        };
+        Some(member)
+    }
+}
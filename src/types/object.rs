@@ -1,14 +1,37 @@
 use crate::types::error::Result;
+use crate::types::members::MemberTable;
 use crate::types::value::Value;
 use std::any::Any;
+use std::cmp::Ordering;
 use std::fmt::{Debug, Display, Formatter};
 
 pub trait Object: Any {
     fn type_name(&self) -> &'static str {
         "object"
     }
+    // declarative members; when present, the default get_member and member_names dispatch through it
+    fn member_table(&self) -> Option<&MemberTable> {
+        None
+    }
     fn get_member(&self, name: &str) -> Result<Value> {
-        Err(crate::types::error::Error::ResolveFailed(name.into()))
+        match self.member_table() {
+            Some(table) => table.lookup(self.type_name(), name),
+            None => Err(crate::types::error::Error::UnknownMember {
+                type_name: self.type_name().into(),
+                member: name.into(),
+            }),
+        }
+    }
+    fn member_names(&self) -> Vec<&'static str> {
+        self.member_table().map(MemberTable::names).unwrap_or_default()
+    }
+    // `obj.field = value`; objects are shared, so one that accepts assignments keeps its state in a `Cell` or
+    // `RefCell`. Read-only by default.
+    fn set_member(&self, name: &str, _value: Value) -> Result<()> {
+        Err(crate::types::error::Error::NotAssignable {
+            type_name: self.type_name().into(),
+            member: name.into(),
+        })
     }
     fn get_index(&self, index: i64) -> Result<Value> {
         Err(crate::types::error::Error::NotIndexable(index.to_string()))
@@ -16,6 +39,12 @@ pub trait Object: Any {
     fn get_key_value(&self, key: &str) -> Result<Value> {
         Err(crate::types::error::Error::NotIndexable(key.into()))
     }
+    // the elements of an object that can be iterated like a list; list members it does not define itself, such
+    // as `contains` and `map`, then work on them, and so does `in`. Not defining a member means `get_member`
+    // reports `UnknownMember` for it: any other error is passed on as it is.
+    fn iter(&self) -> Option<Vec<Value>> {
+        None
+    }
     fn as_string(&self) -> Option<String> {
         None
     }
@@ -34,6 +63,10 @@ pub trait Object: Any {
     fn equals(&self, _other: &Value) -> bool {
         false
     }
+    // ordering used by <, <=, > and >=; `None` means the values are not comparable
+    fn compare(&self, _other: &Value) -> Option<Ordering> {
+        None
+    }
     fn display(&self) -> String {
         self.as_string().unwrap_or_else(|| self.type_name().into())
     }
@@ -1,11 +1,18 @@
 use crate::types::error::{Error, Result};
+use std::hash::{Hash, Hasher};
 
+// non-exhaustive, since the set of variants depends on the enabled features
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Primitive {
     Int(i64),
     Float(f64),
     Str(String),
     Bool(bool),
+    Null,
+    // exact decimal for money math; `1.50m` in source
+    #[cfg(feature = "decimal")]
+    Decimal(rust_decimal::Decimal),
 }
 
 impl Primitive {
@@ -29,6 +36,9 @@ impl Primitive {
             Primitive::Float(f) => Some(*f != 0.0),
             Primitive::Str(s) if s == "true" || s == "false" => Some(s == "true"),
             Primitive::Bool(b) => Some(*b),
+            Primitive::Null => Some(false),
+            #[cfg(feature = "decimal")]
+            Primitive::Decimal(d) => Some(!d.is_zero()),
             _ => None,
         }
     }
@@ -36,6 +46,8 @@ impl Primitive {
         match self {
             Primitive::Float(f) => Some(*f),
             Primitive::Int(i) => Some(*i as f64),
+            #[cfg(feature = "decimal")]
+            Primitive::Decimal(d) => rust_decimal::prelude::ToPrimitive::to_f64(d),
             _ => None,
         }
     }
@@ -43,12 +55,48 @@ impl Primitive {
         match self {
             Primitive::Str(s) => s.clone(),
             Primitive::Int(i) => i.to_string(),
-            Primitive::Float(f) => f.to_string(),
+            Primitive::Float(f) => format_float(*f),
             Primitive::Bool(b) => b.to_string(),
+            Primitive::Null => "null".to_string(),
+            // keeps the scale of the value, e.g. 1.50m prints as 1.50
+            #[cfg(feature = "decimal")]
+            Primitive::Decimal(d) => d.to_string(),
         }
     }
 }
 
+// Canonical float rendering: integral floats print without a fractional part (`2.0` -> "2"), `-0.0` prints as "0",
+// and magnitudes of 1e21 and above or below 1e-6 switch to exponent notation ("1e21", "1.5e-7") instead of a
+// long run of zeros.
+fn format_float(f: f64) -> String {
+    if f == 0.0 {
+        return "0".to_string();
+    }
+    let magnitude = f.abs();
+    if f.is_finite() && !(1e-6..1e21).contains(&magnitude) { format!("{:e}", f) } else { f.to_string() }
+}
+
+// Floats hash by their bit pattern so that expressions can be used as cache keys. `0.0` and `-0.0` compare
+// equal and therefore hash the same. NaN never compares equal to itself, so a NaN literal (which the parser
+// cannot produce, but an AST built by hand can contain) will never be found again in a hash-based cache.
+impl Hash for Primitive {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Primitive::Int(i) => i.hash(state),
+            Primitive::Float(f) => (if *f == 0.0 { 0.0f64 } else { *f }).to_bits().hash(state),
+            Primitive::Str(s) => s.hash(state),
+            Primitive::Bool(b) => b.hash(state),
+            Primitive::Null => {}
+            // Decimal's Hash normalises the scale, matching its Eq (1.5m == 1.50m)
+            #[cfg(feature = "decimal")]
+            Primitive::Decimal(d) => d.hash(state),
+        }
+    }
+}
+
+impl Eq for Primitive {}
+
 impl From<i64> for Primitive {
     fn from(v: i64) -> Self {
         Primitive::Int(v)
@@ -59,6 +107,12 @@ impl From<f64> for Primitive {
         Primitive::Float(v)
     }
 }
+#[cfg(feature = "decimal")]
+impl From<rust_decimal::Decimal> for Primitive {
+    fn from(v: rust_decimal::Decimal) -> Self {
+        Primitive::Decimal(v)
+    }
+}
 impl From<bool> for Primitive {
     fn from(v: bool) -> Self {
         Primitive::Bool(v)
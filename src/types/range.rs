@@ -0,0 +1,186 @@
+use crate::types::error::{Error, Result};
+use crate::types::list;
+use crate::types::members::{Member, Members};
+use crate::types::object::Object;
+use crate::types::primitive::Primitive;
+use crate::types::value::Value;
+use std::any::Any;
+use std::rc::Rc;
+
+/// The most elements `toList` turns a range into, unless `with_limit` says otherwise.
+pub const MAX_LIST_LENGTH: usize = 10_000_000;
+
+/// The half-open range `start..end`. A range whose end is not above its start is empty, so `5..1` has no elements.
+pub fn new(start: i64, end: i64) -> Result<Value> {
+    with_limit(start, end, MAX_LIST_LENGTH)
+}
+
+/// Like `new`, but `toList` fails with `ResultTooLarge` instead of building a list of more than `limit` elements.
+pub fn with_limit(start: i64, end: i64, limit: usize) -> Result<Value> {
+    if end > start && end.checked_sub(start).is_none() {
+        return Err(Error::IntegerOverflow(format!("length of {}..{}", start, end)));
+    }
+    Ok(Value::Object(Rc::new(RangeObject { start, end, limit })))
+}
+
+/// Returns the range behind `value`, if it is one.
+pub fn as_range(value: &Value) -> Option<&RangeObject> {
+    value.downcast_ref::<RangeObject>()
+}
+
+// Elements are computed on demand, so a range never allocates however long it is; only `toList` does, and only up
+// to `limit` elements.
+pub struct RangeObject {
+    start: i64,
+    end: i64,
+    limit: usize,
+}
+
+impl RangeObject {
+    pub fn len(&self) -> usize {
+        if self.end > self.start { (self.end - self.start) as usize } else { 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        matches!(value, Value::Primitive(Primitive::Int(i)) if (self.start..self.end).contains(i))
+    }
+}
+
+impl Members for RangeObject {
+    const TYPE_NAME: &'static str = "range";
+    const MEMBER_NAMES: &'static [&'static str] = &["start", "end", "length", "isEmpty", "contains", "toList"];
+
+    fn member(&self, name: &str) -> Option<Member> {
+        let (start, end, limit) = (self.start, self.end, self.limit);
+        let member = match name {
+            "start" => Member::Property(Value::from(start)),
+            "end" => Member::Property(Value::from(end)),
+            "length" => Member::Property(Value::from(self.len() as i64)),
+            "isEmpty" => Member::Property(Value::from(self.is_empty())),
+            "contains" => Member::method1(move |arg: &Value| Ok(Value::from(RangeObject { start, end, limit }.contains(arg)))),
+            "toList" => {
+                let size = self.len();
+                Member::method0(move || {
+                    if size > limit {
+                        return Err(Error::ResultTooLarge { size, limit });
+                    }
+                    Ok(list::new((start..end).map(Value::from).collect()))
+                })
+            }
+            _ => return None,
+        };
+        Some(member)
+    }
+}
+
+impl Object for RangeObject {
+    fn type_name(&self) -> &'static str {
+        Self::TYPE_NAME
+    }
+
+    fn get_member(&self, name: &str) -> Result<Value> {
+        self.lookup_member(name)
+    }
+
+    fn member_names(&self) -> Vec<&'static str> {
+        Self::MEMBER_NAMES.to_vec()
+    }
+
+    // like a list: negative indices count from the end
+    fn get_index(&self, index: i64) -> Result<Value> {
+        let len = self.len() as i64;
+        let eff = if index < 0 { len + index } else { index };
+        if eff < 0 || eff >= len {
+            return Err(Error::IndexOutOfBounds { index, len: self.len() });
+        }
+        Ok(Value::from(self.start + eff))
+    }
+
+    fn as_string(&self) -> Option<String> {
+        Some(format!("{}..{}", self.start, self.end))
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        Some(!self.is_empty())
+    }
+
+    // ranges with the same elements are equal, so all empty ranges are
+    fn equals(&self, other: &Value) -> bool {
+        as_range(other).is_some_and(|other| (self.is_empty() && other.is_empty()) || (self.start, self.end) == (other.start, other.end))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_contains_and_index() {
+        let r = RangeObject { start: 2, end: 8, limit: MAX_LIST_LENGTH };
+        assert_eq!(r.get_member("length").unwrap(), Value::from(6i64));
+        let contains = |v: Value| r.get_member("contains").unwrap().call(&[v]).unwrap() == Value::from(true);
+        assert!(contains(Value::from(2i64)));
+        assert!(contains(Value::from(7i64)));
+        assert!(!contains(Value::from(8i64)));
+        assert!(!contains(Value::from(1i64)));
+        assert!(!contains(Value::from(3.0)));
+        assert_eq!(r.get_index(0).unwrap(), Value::from(2i64));
+        assert_eq!(r.get_index(5).unwrap(), Value::from(7i64));
+        assert_eq!(r.get_index(-1).unwrap(), Value::from(7i64));
+        assert!(matches!(r.get_index(6), Err(Error::IndexOutOfBounds { index: 6, len: 6 })));
+        assert!(matches!(r.get_index(-7), Err(Error::IndexOutOfBounds { index: -7, len: 6 })));
+    }
+
+    #[test]
+    fn negative_and_descending_bounds() {
+        let r = RangeObject { start: -3, end: 1, limit: MAX_LIST_LENGTH };
+        assert_eq!(r.get_member("toList").unwrap().call(&[]).unwrap().to_string(), "[-3, -2, -1, 0]");
+        for (start, end) in [(5, 1), (4, 4)] {
+            let empty = RangeObject { start, end, limit: MAX_LIST_LENGTH };
+            assert_eq!(empty.get_member("length").unwrap(), Value::from(0i64));
+            assert_eq!(empty.get_member("isEmpty").unwrap(), Value::from(true));
+            assert!(empty.get_index(0).is_err());
+            assert_eq!(new(start, end).unwrap(), new(0, 0).unwrap());
+        }
+        assert_ne!(new(0, 2).unwrap(), new(0, 3).unwrap());
+        // huge ranges are fine as long as the length fits
+        assert_eq!(
+            RangeObject {
+                start: i64::MIN,
+                end: -1,
+                limit: MAX_LIST_LENGTH
+            }
+            .get_index(-1)
+            .unwrap(),
+            Value::from(-2i64)
+        );
+        assert!(matches!(new(i64::MIN, i64::MAX), Err(Error::IntegerOverflow(_))));
+    }
+
+    #[test]
+    fn to_list_limit() {
+        let to_list = |range: Value| range.get_member("toList").unwrap().call(&[]);
+        assert!(matches!(
+            to_list(new(0, 3_000_000_000_000).unwrap()),
+            Err(Error::ResultTooLarge {
+                size: 3_000_000_000_000,
+                limit: MAX_LIST_LENGTH
+            })
+        ));
+        assert!(matches!(to_list(new(i64::MIN, -1).unwrap()), Err(Error::ResultTooLarge { .. })));
+        assert_eq!(to_list(with_limit(0, 5, 5).unwrap()).unwrap().to_string(), "[0, 1, 2, 3, 4]");
+        assert!(matches!(to_list(with_limit(0, 6, 5).unwrap()), Err(Error::ResultTooLarge { size: 6, limit: 5 })));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::types::error::{Error, Result};
+use crate::types::list;
+use crate::types::members::{Member, Members};
+use crate::types::object::Object;
+use crate::types::primitive::Primitive;
+use crate::types::value::Value;
+use std::any::Any;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// Builds a set of `values`, dropping duplicates. Only strings, numbers, bools and null can be elements.
+pub fn from_values(values: Vec<Value>) -> Result<Value> {
+    let items = values
+        .into_iter()
+        .map(|v| match v {
+            Value::Primitive(p) => Ok(p),
+            Value::Object(_) => Err(Error::TypeMismatch(format!("set elements must be strings, numbers, bools or null, got {}", v.type_name()))),
+        })
+        .collect::<Result<_>>()?;
+    Ok(new(items))
+}
+
+pub fn new(items: Vec<Primitive>) -> Value {
+    Value::Object(Rc::new(SetObject::new(items)))
+}
+
+/// Returns the set behind `value`, if it is one.
+pub fn as_set(value: &Value) -> Option<&SetObject> {
+    value.downcast_ref::<SetObject>()
+}
+
+pub struct SetObject {
+    // sorted by `element_cmp`, without duplicates
+    items: Rc<Vec<Primitive>>,
+}
+
+impl SetObject {
+    pub fn new(mut items: Vec<Primitive>) -> SetObject {
+        items.sort_by(element_cmp);
+        items.dedup();
+        SetObject { items: Rc::new(items) }
+    }
+
+    /// The elements in display order: null, bools, numbers (by value), then strings.
+    pub fn items(&self) -> &[Primitive] {
+        &self.items
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        match value {
+            Value::Primitive(p) => self.items.binary_search_by(|e| element_cmp(e, p)).is_ok(),
+            Value::Object(_) => false,
+        }
+    }
+}
+
+// A total order that agrees with `==`: elements compare equal exactly when they are equal. Numbers of different
+// kinds (`1` and `1.0`) are never equal, so equal values order ints before floats before decimals.
+fn element_cmp(a: &Primitive, b: &Primitive) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let by_value = match (a, b) {
+        (Primitive::Int(x), Primitive::Int(y)) => x.cmp(y),
+        (Primitive::Str(x), Primitive::Str(y)) => x.cmp(y),
+        (Primitive::Bool(x), Primitive::Bool(y)) => x.cmp(y),
+        #[cfg(feature = "decimal")]
+        (Primitive::Decimal(x), Primitive::Decimal(y)) => x.cmp(y),
+        _ => match (a.to_float_lossy(), b.to_float_lossy()) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            _ => Ordering::Equal,
+        },
+    };
+    rank(a).0.cmp(&rank(b).0).then(by_value).then(rank(a).1.cmp(&rank(b).1))
+}
+
+// (group, kind): the group orders null, bools, numbers and strings; the kind separates ints, floats and decimals
+fn rank(p: &Primitive) -> (u8, u8) {
+    match p {
+        Primitive::Null => (0, 0),
+        Primitive::Bool(_) => (1, 0),
+        Primitive::Int(_) => (2, 0),
+        Primitive::Float(_) => (2, 1),
+        #[cfg(feature = "decimal")]
+        Primitive::Decimal(_) => (2, 2),
+        Primitive::Str(_) => (3, 0),
+    }
+}
+
+impl Members for SetObject {
+    const TYPE_NAME: &'static str = "set";
+    const MEMBER_NAMES: &'static [&'static str] = &["length", "isEmpty", "contains", "union", "intersection", "difference", "toList"];
+
+    fn member(&self, name: &str) -> Option<Member> {
+        let member = match name {
+            "length" => Member::Property(Value::from(self.items.len() as i64)),
+            "isEmpty" => Member::Property(Value::from(self.items.is_empty())),
+            "contains" => {
+                let set = SetObject { items: Rc::clone(&self.items) };
+                Member::method1(move |arg: &Value| Ok(Value::from(set.contains(arg))))
+            }
+            "union" => {
+                let base = Rc::clone(&self.items);
+                Member::method1(move |arg: &Value| {
+                    let other = other_set(arg, "union")?;
+                    Ok(new(base.iter().chain(other.items.iter()).cloned().collect()))
+                })
+            }
+            "intersection" => {
+                let base = Rc::clone(&self.items);
+                Member::method1(move |arg: &Value| {
+                    let other = other_set(arg, "intersection")?;
+                    Ok(new(base.iter().filter(|p| other.contains(&Value::Primitive((*p).clone()))).cloned().collect()))
+                })
+            }
+            "difference" => {
+                let base = Rc::clone(&self.items);
+                Member::method1(move |arg: &Value| {
+                    let other = other_set(arg, "difference")?;
+                    Ok(new(base.iter().filter(|p| !other.contains(&Value::Primitive((*p).clone()))).cloned().collect()))
+                })
+            }
+            "toList" => {
+                let base = Rc::clone(&self.items);
+                Member::method0(move || Ok(list::new(base.iter().cloned().map(Value::Primitive).collect())))
+            }
+            _ => return None,
+        };
+        Some(member)
+    }
+}
+
+fn other_set<'v>(arg: &'v Value, method: &str) -> Result<&'v SetObject> {
+    as_set(arg).ok_or_else(|| Error::TypeMismatch(format!("{} expects a set, got {}", method, arg.type_name())))
+}
+
+impl Object for SetObject {
+    fn type_name(&self) -> &'static str {
+        Self::TYPE_NAME
+    }
+
+    fn get_member(&self, name: &str) -> Result<Value> {
+        self.lookup_member(name)
+    }
+
+    fn member_names(&self) -> Vec<&'static str> {
+        Self::MEMBER_NAMES.to_vec()
+    }
+
+    fn as_string(&self) -> Option<String> {
+        Some(format!("{{{}}}", self.items.iter().map(|p| p.as_str_lossy()).collect::<Vec<_>>().join(", ")))
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        Some(!self.items.is_empty())
+    }
+
+    // elements are kept sorted, so insertion order never matters
+    fn equals(&self, other: &Value) -> bool {
+        as_set(other).is_some_and(|other| self.items == other.items)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(items: &[Value]) -> Value {
+        from_values(items.to_vec()).unwrap()
+    }
+
+    fn call(receiver: &Value, method: &str, arg: &Value) -> Result<Value> {
+        receiver.get_member(method)?.call(std::slice::from_ref(arg))
+    }
+
+    #[test]
+    fn elements_are_sorted_and_unique() {
+        let s = set(&[
+            Value::from("b"),
+            Value::from(2i64),
+            Value::from(1.5),
+            Value::from("a"),
+            Value::from(2i64),
+            Value::from(true),
+            Value::Primitive(Primitive::Null),
+            Value::from(2.0),
+        ]);
+        // the int 2 and the float 2.0 are different elements, though both print as 2
+        assert_eq!(s.to_string(), "{null, true, 1.5, 2, 2, a, b}");
+        assert_eq!(s.get_member("length").unwrap(), Value::from(7i64));
+        assert!(from_values(vec![list::new(vec![])]).is_err());
+    }
+
+    #[test]
+    fn membership() {
+        let s = set(&[Value::from(1i64), Value::from("x")]);
+        let contains = |v: Value| call(&s, "contains", &v).unwrap() == Value::from(true);
+        assert!(contains(Value::from(1i64)));
+        assert!(contains(Value::from("x")));
+        assert!(!contains(Value::from(1.0)));
+        assert!(!contains(Value::from("1")));
+        assert!(!contains(list::new(vec![Value::from(1i64)])));
+    }
+
+    #[test]
+    fn algebra() {
+        let ints = |xs: &[i64]| set(&xs.iter().map(|x| Value::from(*x)).collect::<Vec<_>>());
+        let (a, b) = (ints(&[1, 2, 3]), ints(&[3, 4]));
+        assert_eq!(call(&a, "union", &b).unwrap(), ints(&[1, 2, 3, 4]));
+        assert_eq!(call(&a, "intersection", &b).unwrap(), ints(&[3]));
+        assert_eq!(call(&a, "difference", &b).unwrap(), ints(&[1, 2]));
+        assert_eq!(call(&b, "difference", &a).unwrap(), ints(&[4]));
+        assert_eq!(call(&a, "difference", &a).unwrap().get_member("isEmpty").unwrap(), Value::from(true));
+        // equality ignores the order elements were given in
+        assert_eq!(ints(&[3, 1, 2]), a);
+        assert_ne!(ints(&[1, 2]), a);
+        assert!(matches!(call(&a, "union", &list::new(vec![])), Err(Error::TypeMismatch(_))));
+    }
+}
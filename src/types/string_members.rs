@@ -1,79 +1,442 @@
 use crate::types::error::{Error, Result};
-use crate::types::function;
+use crate::types::list;
+use crate::types::members::{Member, Members, slice_bounds};
 use crate::types::primitive::Primitive;
 use crate::types::value::Value;
-use std::rc::Rc;
-
-pub fn get_string_member(value: &str, name: &str) -> Result<Value> {
-    match name {
-        "length" => Ok(Value::from(value.len() as i64)),
-        "toUpper" => {
-            let base = value.to_string();
-            Ok(function::method0(move || Ok(Value::from(base.to_uppercase()))))
-        }
-        "toLower" => {
-            let base = value.to_string();
-            Ok(function::method0(move || Ok(Value::from(base.to_lowercase()))))
-        }
-        "trim" => {
-            let base = value.to_string();
-            Ok(function::method0(move || Ok(Value::from(base.trim().to_string()))))
-        }
-        "contains" => {
-            let base = value.to_string();
-            Ok(function::method1(move |arg: &Value| {
-                if let Value::Primitive(Primitive::Str(s)) = arg {
-                    Ok(Value::from(base.contains(s)))
-                } else {
-                    Err(Error::TypeMismatch("contains expects a string".into()))
-                }
-            }))
-        }
-        "substring" => {
-            let base = value.to_string();
-            Ok(function::new(Rc::new(move |args: &[Value]| {
-                if args.is_empty() || args.len() > 2 {
-                    return Err(Error::EvaluationFailed("expected 1 or 2 args".into()));
-                }
-                // Collect chars for safe slicing
-                let chars: Vec<char> = base.chars().collect();
-                let len = chars.len() as i64;
-                // start index
-                let start_i = match &args[0] {
-                    Value::Primitive(Primitive::Int(i)) => *i,
-                    _ => return Err(Error::TypeMismatch("substring expects int start".into())),
-                };
-                let mut start = if start_i < 0 { len + start_i } else { start_i };
-                if start < 0 {
-                    start = 0;
-                }
-                if start > len {
-                    start = len;
-                }
-                // end index (exclusive)
-                let mut end = len;
-                if args.len() == 2 {
-                    match &args[1] {
-                        Value::Primitive(Primitive::Int(i)) => {
-                            let e = if *i < 0 { len + *i } else { *i };
-                            end = e.max(0).min(len);
+use std::cmp::Ordering;
+
+impl Members for str {
+    const TYPE_NAME: &'static str = "string";
+    const MEMBER_NAMES: &'static [&'static str] = &[
+        "length",
+        "byteLength",
+        "isEmpty",
+        "isBlank",
+        "toUpper",
+        "toLower",
+        "trim",
+        "contains",
+        "substring",
+        "slice",
+        "lines",
+        "split",
+        "capitalize",
+        "title",
+        "startsWith",
+        "endsWith",
+        "indexOf",
+        "equalsIgnoreCase",
+        #[cfg(feature = "regex")]
+        "matches",
+        #[cfg(feature = "regex")]
+        "find",
+        #[cfg(feature = "regex")]
+        "replaceAll",
+        "toInt",
+        "toFloat",
+    ];
+
+    fn member(&self, name: &str) -> Option<Member> {
+        let member = match name {
+            // in characters, like `substring` and indexing; `byteLength` is the size of the UTF-8 encoding
+            "length" => Member::Property(Value::from(self.chars().count() as i64)),
+            "byteLength" => Member::Property(Value::from(self.len() as i64)),
+            "isEmpty" => Member::Property(Value::from(self.is_empty())),
+            "isBlank" => Member::Property(Value::from(self.trim().is_empty())),
+            "toUpper" => {
+                let base = self.to_string();
+                Member::method0(move || Ok(Value::from(base.to_uppercase())))
+            }
+            "toLower" => {
+                let base = self.to_string();
+                Member::method0(move || Ok(Value::from(base.to_lowercase())))
+            }
+            "trim" => {
+                let base = self.to_string();
+                Member::method0(move || Ok(Value::from(base.trim().to_string())))
+            }
+            "contains" => {
+                let base = self.to_string();
+                Member::method1(move |arg: &Value| {
+                    if let Value::Primitive(Primitive::Str(s)) = arg {
+                        return Ok(Value::from(base.contains(s.as_str())));
+                    }
+                    // a list of needles matches if any of them occurs
+                    let Some(needles) = list::as_list(arg) else {
+                        return Err(Error::TypeMismatch("contains expects a string or a list of strings".into()));
+                    };
+                    let mut found = false;
+                    for needle in needles.items() {
+                        let Value::Primitive(Primitive::Str(s)) = needle else {
+                            return Err(Error::TypeMismatch("contains expects a list of strings".into()));
+                        };
+                        found = found || base.contains(s.as_str());
+                    }
+                    Ok(Value::from(found))
+                })
+            }
+            "substring" => Member::method(slice_method(self.to_string(), "substring")),
+            "slice" => Member::method(slice_method(self.to_string(), "slice")),
+            "lines" => {
+                let base = self.to_string();
+                // str::lines splits on '\n', drops a trailing '\r' and ignores a final newline
+                Member::method0(move || Ok(list::new(base.lines().map(Value::from).collect())))
+            }
+            "split" => {
+                let base = self.to_string();
+                Member::method1(move |arg: &Value| {
+                    let Value::Primitive(Primitive::Str(sep)) = arg else {
+                        return Err(Error::TypeMismatch("split expects a string separator".into()));
+                    };
+                    // an empty separator splits into single characters
+                    let parts: Vec<Value> = if sep.is_empty() {
+                        base.chars().map(|c| Value::from(c.to_string())).collect()
+                    } else {
+                        base.split(sep.as_str()).map(Value::from).collect()
+                    };
+                    Ok(list::new(parts))
+                })
+            }
+            "capitalize" => {
+                let base = self.to_string();
+                Member::method0(move || Ok(Value::from(capitalize(&base))))
+            }
+            "title" => {
+                let base = self.to_string();
+                Member::method0(move || {
+                    // capitalize every whitespace-delimited word, keeping the whitespace itself intact
+                    let mut out = String::with_capacity(base.len());
+                    let mut word_start = true;
+                    for c in base.chars() {
+                        if word_start {
+                            out.extend(c.to_uppercase());
+                        } else {
+                            out.extend(c.to_lowercase());
                         }
-                        _ => return Err(Error::TypeMismatch("substring expects int end".into())),
+                        word_start = c.is_whitespace();
                     }
-                }
-                if start > end {
-                    // empty
-                    return Ok(Value::from(String::new()));
-                }
-                let sidx = start as usize;
-                let eidx = end as usize;
-                let sub: String = chars[sidx..eidx].iter().collect();
-                Ok(Value::from(sub))
-            })))
+                    Ok(Value::from(out))
+                })
+            }
+            "startsWith" => Member::method1(predicate_method(self.to_string(), "startsWith", |s, p| s.starts_with(p))),
+            "endsWith" => Member::method1(predicate_method(self.to_string(), "endsWith", |s, p| s.ends_with(p))),
+            // full Unicode lowercasing, so "STRASSE" does not equal "straße" but "Ὀδυσσεύς" equals "ὈΔΥΣΣΕΎΣ"
+            "equalsIgnoreCase" => Member::method1(predicate_method(self.to_string(), "equalsIgnoreCase", |s, o| s.to_lowercase() == o.to_lowercase())),
+            "indexOf" => {
+                let base = self.to_string();
+                Member::method1(move |arg: &Value| {
+                    let Value::Primitive(Primitive::Str(needle)) = arg else {
+                        return Err(Error::TypeMismatch("indexOf expects a string".into()));
+                    };
+                    // a char index, so it can be passed straight to substring/slice
+                    let index = base.find(needle.as_str()).map_or(-1, |byte| base[..byte].chars().count() as i64);
+                    Ok(Value::from(index))
+                })
+            }
+            #[cfg(feature = "regex")]
+            "matches" => {
+                let base = self.to_string();
+                Member::method1(move |arg: &Value| {
+                    // anchored, so the pattern has to match the whole string rather than some part of it
+                    // the pattern is compiled on its own first, or `a)|(b` would slip through as `\A(?:a)|(b)\z`
+                    let pattern = pattern_arg(arg, "matches")?;
+                    compile(pattern)?;
+                    let whole = regex::Regex::new(&format!(r"\A(?:{})\z", pattern)).map_err(|_| invalid_pattern(pattern))?;
+                    Ok(Value::from(whole.is_match(&base)))
+                })
+            }
+            #[cfg(feature = "regex")]
+            "find" => {
+                let base = self.to_string();
+                Member::method1(move |arg: &Value| {
+                    let found = compile(pattern_arg(arg, "find")?)?.find(&base).map(|m| Value::from(m.as_str()));
+                    Ok(found.unwrap_or(Value::Primitive(Primitive::Null)))
+                })
+            }
+            #[cfg(feature = "regex")]
+            "replaceAll" => {
+                let base = self.to_string();
+                Member::method(move |args: &[Value]| {
+                    let [pattern, replacement] = args else {
+                        return Err(Error::EvaluationFailed("expected 2 args".into()));
+                    };
+                    let Value::Primitive(Primitive::Str(replacement)) = replacement else {
+                        return Err(Error::TypeMismatch("replaceAll expects a string replacement".into()));
+                    };
+                    // `$1`, `${1}` and `${name}` in the replacement refer to capture groups; `$$` is a literal '$'
+                    let re = compile(pattern_arg(pattern, "replaceAll")?)?;
+                    Ok(Value::from(re.replace_all(&base, replacement.as_str()).into_owned()))
+                })
+            }
+            "toInt" => {
+                let base = self.to_string();
+                Member::method0(move || base.trim().parse::<i64>().map(Value::from).map_err(|_| Error::EvaluationFailed(format!("toInt: not an integer: {:?}", base))))
+            }
+            "toFloat" => {
+                let base = self.to_string();
+                Member::method0(move || match base.trim().parse::<f64>() {
+                    Ok(f) if f.is_finite() => Ok(Value::from(f)),
+                    _ => Err(Error::EvaluationFailed(format!("toFloat: not a number: {:?}", base))),
+                })
+            }
+            _ => return None,
+        };
+        Some(member)
+    }
+}
+
+fn slice_method(base: String, method: &'static str) -> impl Fn(&[Value]) -> Result<Value> {
+    move |args: &[Value]| {
+        // Collect chars for safe slicing
+        let chars: Vec<char> = base.chars().collect();
+        let (start, end) = slice_bounds(chars.len(), args, method)?;
+        Ok(Value::from(chars[start..end].iter().collect::<String>()))
+    }
+}
+
+// A method testing the string against a string argument. startsWith/endsWith are plain prefix/suffix tests on the
+// string's bytes, so an empty affix always matches.
+fn predicate_method(base: String, method: &'static str, test: fn(&str, &str) -> bool) -> impl Fn(&Value) -> Result<Value> {
+    move |arg: &Value| {
+        let Value::Primitive(Primitive::Str(other)) = arg else {
+            return Err(Error::TypeMismatch(format!("{} expects a string", method)));
+        };
+        Ok(Value::from(test(&base, other)))
+    }
+}
+
+#[cfg(feature = "regex")]
+fn pattern_arg<'v>(arg: &'v Value, method: &str) -> Result<&'v str> {
+    match arg {
+        Value::Primitive(Primitive::Str(pattern)) => Ok(pattern),
+        _ => Err(Error::TypeMismatch(format!("{} expects a string pattern", method))),
+    }
+}
+
+// Patterns use the syntax of the regex crate; a pattern that does not compile reports the compiler's message.
+#[cfg(feature = "regex")]
+fn compile(pattern: &str) -> Result<regex::Regex> {
+    regex::Regex::new(pattern).map_err(|e| Error::EvaluationFailed(format!("invalid pattern: {}", e)))
+}
+
+#[cfg(feature = "regex")]
+fn invalid_pattern(pattern: &str) -> Error {
+    Error::EvaluationFailed(format!("invalid pattern: {:?}", pattern))
+}
+
+// Uppercases the first character and lowercases the rest. A leading whitespace character stays as it is.
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+// Indexing a string yields the single character at that (char) position; negative indices count from the end.
+pub(crate) fn get_string_index(value: &str, index: i64) -> Result<Value> {
+    let len = value.chars().count();
+    let eff = if index < 0 { len as i64 + index } else { index };
+    if eff < 0 || eff >= len as i64 {
+        return Err(Error::IndexOutOfBounds { index, len });
+    }
+    Ok(Value::from(value.chars().nth(eff as usize).unwrap().to_string()))
+}
+
+// Natural ("numeric-aware") ordering: runs of ASCII digits compare by their numeric value, so "file2" sorts
+// before "file10" and "1.9" before "1.10"; everything else compares by character. Numbers that differ only in
+// leading zeros ("01" and "1") fall back to the plain ordering, which keeps this a total order.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a, b);
+    loop {
+        let (Some(c), Some(d)) = (x.chars().next(), y.chars().next()) else {
+            return x.len().cmp(&y.len()).then_with(|| a.cmp(b));
+        };
+        if c.is_ascii_digit() && d.is_ascii_digit() {
+            let (xn, xrest) = x.split_at(x.find(|c: char| !c.is_ascii_digit()).unwrap_or(x.len()));
+            let (yn, yrest) = y.split_at(y.find(|c: char| !c.is_ascii_digit()).unwrap_or(y.len()));
+            let (xn, yn) = (xn.trim_start_matches('0'), yn.trim_start_matches('0'));
+            let ord = xn.len().cmp(&yn.len()).then_with(|| xn.cmp(yn));
+            if ord.is_ne() {
+                return ord;
+            }
+            (x, y) = (xrest, yrest);
+        } else if c != d {
+            return c.cmp(&d);
+        } else {
+            (x, y) = (&x[c.len_utf8()..], &y[d.len_utf8()..]);
         }
-        _ => Err(Error::UnknownMember {
-            type_name: "string".into(),
-            member: name.to_string(),
-        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(receiver: &str, method: &str) -> Value {
+        let Ok(Value::Object(f)) = receiver.lookup_member(method) else {
+            panic!("{} is not a method", method);
+        };
+        f.call(&[]).unwrap()
+    }
+
+    fn call1(receiver: &str, method: &str, arg: Value) -> Result<Value> {
+        let Ok(Value::Object(f)) = receiver.lookup_member(method) else {
+            panic!("{} is not a method", method);
+        };
+        f.call(&[arg])
+    }
+
+    #[test]
+    fn length_counts_characters() {
+        let prop = |r: &str, m: &str| r.lookup_member(m).unwrap();
+        assert_eq!(prop("héllo", "length"), Value::from(5i64));
+        assert_eq!(prop("héllo", "byteLength"), Value::from(6i64));
+        assert_eq!(prop("🎉", "length"), Value::from(1i64));
+        assert_eq!(prop("🎉", "byteLength"), Value::from(4i64));
+        assert_eq!(prop("", "length"), Value::from(0i64));
+    }
+
+    #[test]
+    fn starts_and_ends_with() {
+        let yes = |r: &str, m: &str, a: &str| call1(r, m, Value::from(a)).unwrap() == Value::from(true);
+        assert!(yes("/api/users", "startsWith", "/api"));
+        assert!(!yes("/api/users", "startsWith", "api"));
+        assert!(yes("report.csv", "endsWith", ".csv"));
+        assert!(!yes("report.csv", "endsWith", ".CSV"));
+        assert!(yes("abc", "startsWith", ""));
+        assert!(yes("", "endsWith", ""));
+        assert!(!yes("", "startsWith", "a"));
+        // multi-byte characters match whole, never a partial encoding
+        assert!(yes("héllo", "startsWith", "hé"));
+        assert!(!yes("héllo", "startsWith", "he"));
+        assert!(yes("naïve 🎉", "endsWith", "🎉"));
+        assert!(yes("e\u{301}", "startsWith", "e"));
+        assert!(matches!(call1("abc", "startsWith", Value::from(1i64)), Err(Error::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn equals_ignore_case() {
+        let same = |r: &str, a: &str| call1(r, "equalsIgnoreCase", Value::from(a)).unwrap() == Value::from(true);
+        assert!(same("Content-Type", "content-TYPE"));
+        assert!(same("", ""));
+        assert!(same("ÉTÉ", "été"));
+        assert!(same("Ὀδυσσεύς", "ὈΔΥΣΣΕΎΣ"));
+        assert!(!same("abc", "abcd"));
+        assert!(!same("STRASSE", "straße"));
+        assert!(matches!(call1("true", "equalsIgnoreCase", Value::from(true)), Err(Error::TypeMismatch(_))));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_matches_and_find() {
+        let matches = |r: &str, p: &str| call1(r, "matches", Value::from(p));
+        assert_eq!(matches("2024-03-01", r"\d{4}-\d{2}-\d{2}").unwrap(), Value::from(true));
+        // the whole string has to match, whichever alternative comes first
+        assert_eq!(matches("2024-03-01 ", r"\d{4}-\d{2}-\d{2}").unwrap(), Value::from(false));
+        assert_eq!(matches("ab", "a|ab").unwrap(), Value::from(true));
+        assert_eq!(matches("", "").unwrap(), Value::from(true));
+        // a pattern that does not compile on its own is rejected, even if it would once anchored
+        assert!(matches("axxx", "a)|(b").is_err());
+        assert!(call1("axxx", "find", Value::from("a)|(b")).is_err());
+
+        let find = |r: &str, p: &str| call1(r, "find", Value::from(p));
+        assert_eq!(find("order #1234, item 56", r"\d+").unwrap(), Value::from("1234"));
+        assert_eq!(find("héllo", "[éè]ll").unwrap(), Value::from("éll"));
+        assert_eq!(find("abc", r"\d").unwrap(), Value::Primitive(Primitive::Null));
+
+        for method in ["matches", "find"] {
+            let err = call1("abc", method, Value::from("(unclosed")).unwrap_err();
+            assert!(matches!(&err, Error::EvaluationFailed(msg) if msg.contains("invalid pattern") && msg.contains("(unclosed")), "{}", err);
+            assert!(matches!(call1("abc", method, Value::from(1i64)), Err(Error::TypeMismatch(_))));
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_replace_all() {
+        let replace = |r: &str, p: &str, with: &str| r.lookup_member("replaceAll").unwrap().call(&[Value::from(p), Value::from(with)]);
+        assert_eq!(replace("card 4111-1111-1111-1234", r"\d", "#").unwrap(), Value::from("card ####-####-####-####"));
+        assert_eq!(replace("2024-03-01", r"(\d+)-(\d+)-(\d+)", "$3/$2/$1").unwrap(), Value::from("01/03/2024"));
+        assert_eq!(replace("Doe, Jane", r"(?<last>\w+), (?<first>\w+)", "${first} ${last}").unwrap(), Value::from("Jane Doe"));
+        assert_eq!(replace("a1b2", r"(\d)", "${1}0$$").unwrap(), Value::from("a10$b20$"));
+        assert_eq!(replace("abc", "x", "y").unwrap(), Value::from("abc"));
+        assert!(matches!(replace("abc", "[", "y"), Err(Error::EvaluationFailed(msg)) if msg.contains("invalid pattern")));
+        assert!(matches!("abc".lookup_member("replaceAll").unwrap().call(&[Value::from("a"), Value::from(1i64)]), Err(Error::TypeMismatch(_))));
+        assert!(matches!("abc".lookup_member("replaceAll").unwrap().call(&[Value::from("a")]), Err(Error::EvaluationFailed(_))));
+    }
+
+    #[test]
+    fn index_of() {
+        let index = |r: &str, a: &str| call1(r, "indexOf", Value::from(a)).unwrap();
+        assert_eq!(index("héllo", "llo"), Value::from(2i64));
+        assert_eq!(index("abcabc", "c"), Value::from(2i64));
+        assert_eq!(index("abc", "x"), Value::from(-1i64));
+        assert_eq!(index("abc", ""), Value::from(0i64));
+        assert_eq!(index("🎉🎉x", "x"), Value::from(2i64));
+        assert!(matches!(call1("abc", "indexOf", Value::from(1i64)), Err(Error::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn to_int_and_float() {
+        let parse = |r: &str, m: &str| r.lookup_member(m).unwrap().call(&[]);
+        assert_eq!(parse("42", "toInt").unwrap(), Value::from(42i64));
+        assert_eq!(parse(" -7\n", "toInt").unwrap(), Value::from(-7i64));
+        assert_eq!(parse("3.5", "toFloat").unwrap(), Value::from(3.5));
+        assert_eq!(parse("42", "toFloat").unwrap(), Value::from(42.0));
+        assert_eq!(parse("1e3", "toFloat").unwrap(), Value::from(1000.0));
+        for (text, method) in [
+            ("3.5", "toInt"),
+            ("", "toInt"),
+            ("12abc", "toInt"),
+            ("99999999999999999999", "toInt"),
+            ("abc", "toFloat"),
+            ("inf", "toFloat"),
+            ("NaN", "toFloat"),
+        ] {
+            match parse(text, method) {
+                Err(Error::EvaluationFailed(msg)) => assert!(msg.contains(&format!("{:?}", text)), "{}", msg),
+                other => panic!("{:?}.{}() should fail, got {:?}", text, method, other),
+            }
+        }
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(call("a\r\nb\nc\n", "lines"), list::new(vec![Value::from("a"), Value::from("b"), Value::from("c")]));
+        assert_eq!(call("a\n\nb", "lines"), list::new(vec![Value::from("a"), Value::from(""), Value::from("b")]));
+        assert_eq!(call("single", "lines"), list::new(vec![Value::from("single")]));
+        assert_eq!(call("", "lines"), list::new(vec![]));
+    }
+
+    #[test]
+    fn capitalize_and_title() {
+        assert_eq!(call("hello WORLD", "capitalize"), Value::from("Hello world"));
+        assert_eq!(call("hELLO world", "capitalize"), Value::from("Hello world"));
+        assert_eq!(call("hELLO world", "title"), Value::from("Hello World"));
+        assert_eq!(call("", "title"), Value::from(""));
+        assert_eq!(call("Hello", "capitalize"), Value::from("Hello"));
+        assert_eq!(call("  hello", "capitalize"), Value::from("  hello"));
+        assert_eq!(call("ßtraße", "capitalize"), Value::from("SStraße"));
+        assert_eq!(call("", "capitalize"), Value::from(""));
+
+        assert_eq!(call("hello big\tWORLD", "title"), Value::from("Hello Big\tWorld"));
+        assert_eq!(call("  leading  spaces ", "title"), Value::from("  Leading  Spaces "));
+        assert_eq!(call("Already Title", "title"), Value::from("Already Title"));
+        assert_eq!(call("élan vital", "title"), Value::from("Élan Vital"));
+    }
+
+    #[test]
+    fn natural_ordering() {
+        use std::cmp::Ordering::*;
+        assert_eq!(natural_cmp("file2", "file10"), Less);
+        assert_eq!(natural_cmp("file10", "file2"), Greater);
+        assert_eq!(natural_cmp("1.9.2", "1.10.0"), Less);
+        assert_eq!(natural_cmp("v1.2", "v1.2.1"), Less);
+        assert_eq!(natural_cmp("a", "b"), Less);
+        assert_eq!(natural_cmp("abc", "abc"), Equal);
+        assert_eq!(natural_cmp("img007", "img7"), Less);
+        assert_eq!(natural_cmp("x99999999999999999999999", "x100000000000000000000000"), Less);
+        assert_eq!(natural_cmp("", "0"), Less);
     }
 }
@@ -0,0 +1,19 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Number,
+    String,
+    Keyword,
+    Ident,
+    Operator,
+    Punctuation,
+    /// Input that cannot start any token, or a string literal that is missing its closing quote
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Byte offsets into the tokenized input, end exclusive
+    pub start: usize,
+    pub end: usize,
+}
@@ -1,11 +1,17 @@
+#[cfg(not(feature = "serde"))]
+use crate::types::dict::DictObject;
 use crate::types::error::{Error, Result};
+use crate::types::list::{self, ListObject};
+use crate::types::members::Members;
 pub(crate) use crate::types::object::Object;
 use crate::types::primitive::Primitive;
-use crate::types::string_members::get_string_member;
+#[cfg(not(feature = "serde"))]
+use crate::types::set;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
 use std::rc::Rc;
 
+/// Cloning a `Value` is cheap: objects (lists, dicts, functions, ...) are shared behind an `Rc` and never deep-copied.
 #[derive(Clone)]
 pub enum Value {
     Primitive(Primitive),
@@ -36,21 +42,163 @@ impl Value {
         match self {
             Value::Primitive(Primitive::Str(_)) => "string",
             Value::Primitive(Primitive::Int(_)) | Value::Primitive(Primitive::Float(_)) => "number",
+            #[cfg(feature = "decimal")]
+            Value::Primitive(Primitive::Decimal(_)) => "number",
             Value::Primitive(Primitive::Bool(_)) => "bool",
+            Value::Primitive(Primitive::Null) => "null",
             Value::Object(obj) => obj.type_name(),
         }
     }
 
     pub fn get_member(&self, name: &str) -> Result<Value> {
         match self {
-            Value::Primitive(Primitive::Str(s)) => get_string_member(s, name),
-            Value::Object(obj) => obj.get_member(name),
+            Value::Primitive(Primitive::Str(s)) => s.as_str().lookup_member(name),
+            Value::Primitive(Primitive::Int(i)) => i.lookup_member(name),
+            Value::Primitive(Primitive::Float(f)) => f.lookup_member(name),
+            Value::Object(obj) => {
+                let member = obj.get_member(name);
+                if matches!(member, Err(Error::UnknownMember { .. }))
+                    && ListObject::MEMBER_NAMES.contains(&name)
+                    && let Some(items) = obj.iter()
+                {
+                    return list::new(items).get_member(name);
+                }
+                member
+            }
             _ => Err(Error::UnknownMember {
                 type_name: self.type_name().into(),
                 member: name.to_string(),
             }),
         }
     }
+
+    /// Assigns to a member of this value, as `obj.field = value` does. Only objects that implement
+    /// `Object::set_member` accept assignments.
+    pub fn set_member(&self, name: &str, value: Value) -> Result<()> {
+        match self {
+            Value::Object(obj) => obj.set_member(name, value),
+            _ => Err(Error::NotAssignable {
+                type_name: self.type_name().into(),
+                member: name.to_string(),
+            }),
+        }
+    }
+
+    /// Returns the concrete object behind this value, e.g. `value.downcast_ref::<ListObject>()`, or `None` for
+    /// primitives and objects of another type.
+    pub fn downcast_ref<T: Object>(&self) -> Option<&T> {
+        match self {
+            Value::Object(obj) => obj.as_any().downcast_ref::<T>(),
+            _ => None,
+        }
+    }
+
+    /// Calls this value as a function. Only function-like objects are callable.
+    pub fn call(&self, args: &[Value]) -> Result<Value> {
+        match self {
+            Value::Object(obj) => obj.call(args),
+            _ => Err(Error::NotCallable),
+        }
+    }
+
+    /// Names of the members (properties and methods) available on this value, e.g. for autocompletion.
+    pub fn member_names(&self) -> Vec<&'static str> {
+        match self {
+            Value::Primitive(Primitive::Str(_)) => <str as Members>::MEMBER_NAMES.to_vec(),
+            Value::Primitive(Primitive::Int(_) | Primitive::Float(_)) => <i64 as Members>::MEMBER_NAMES.to_vec(),
+            Value::Object(obj) => obj.member_names(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Serializes the value as JSON text; unlike the `serde` conversions this needs no feature. Dicts become
+    /// objects with their keys in sorted order, lists and sets arrays, and decimals strings so that no precision is
+    /// lost. Functions, other objects and non-finite floats have no JSON form and are a `TypeMismatch`.
+    pub fn to_json_string(&self) -> Result<String> {
+        // with `serde`, the conversion to `serde_json::Value` is the one place that knows the JSON form
+        #[cfg(feature = "serde")]
+        return Ok(serde_json::Value::try_from(self)?.to_string());
+        #[cfg(not(feature = "serde"))]
+        {
+            let mut out = String::new();
+            write_json(self, &mut out)?;
+            Ok(out)
+        }
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_json(value: &Value, out: &mut String) -> Result<()> {
+    match value {
+        Value::Primitive(p) => write_json_primitive(p, out)?,
+        Value::Object(_) => {
+            if let Some(l) = list::as_list(value) {
+                write_json_array(l.items().iter(), out)?;
+            } else if let Some(s) = set::as_set(value) {
+                write_json_array(s.items().iter().cloned().map(Value::Primitive), out)?;
+            } else if let Some(d) = value.downcast_ref::<DictObject>() {
+                out.push('{');
+                for (i, (key, item)) in d.entries().iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_string(key, out);
+                    out.push(':');
+                    write_json(item, out)?;
+                }
+                out.push('}');
+            } else {
+                return Err(Error::TypeMismatch(format!("cannot convert {} to JSON", value.type_name())));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_json_array<V: std::borrow::Borrow<Value>>(items: impl Iterator<Item = V>, out: &mut String) -> Result<()> {
+    out.push('[');
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_json(item.borrow(), out)?;
+    }
+    out.push(']');
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_json_primitive(p: &Primitive, out: &mut String) -> Result<()> {
+    match p {
+        Primitive::Null => out.push_str("null"),
+        Primitive::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Primitive::Int(i) => out.push_str(&i.to_string()),
+        // the debug form keeps the `.0` of whole floats and uses exponents JSON understands
+        Primitive::Float(f) if f.is_finite() => out.push_str(&format!("{:?}", f)),
+        Primitive::Float(f) => return Err(Error::TypeMismatch(format!("{} has no JSON representation", f))),
+        Primitive::Str(s) => write_json_string(s, out),
+        #[cfg(feature = "decimal")]
+        Primitive::Decimal(d) => write_json_string(&d.to_string(), out),
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
 }
 
 impl Display for Primitive {
@@ -98,6 +246,12 @@ impl From<f64> for Value {
         Value::Primitive(v.into())
     }
 }
+#[cfg(feature = "decimal")]
+impl From<rust_decimal::Decimal> for Value {
+    fn from(v: rust_decimal::Decimal) -> Self {
+        Value::Primitive(v.into())
+    }
+}
 impl From<bool> for Value {
     fn from(v: bool) -> Self {
         Value::Primitive(v.into())
@@ -138,3 +292,38 @@ impl TryFrom<Value> for String {
         if let Value::Primitive(p) = v { p.try_into() } else { Err(Error::TypeMismatch("expected string".into())) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{dict, function, set};
+
+    #[test]
+    fn json_string() {
+        let nested = dict::new(
+            [
+                ("name".to_string(), Value::from("say \"hi\"\n\\ \u{1}")),
+                ("count".to_string(), Value::from(3i64)),
+                ("ratio".to_string(), Value::from(2.0)),
+                ("tiny".to_string(), Value::from(1e-9)),
+                ("tags".to_string(), list::new(vec![Value::from(true), Value::Primitive(Primitive::Null), list::new(vec![])])),
+                ("ids".to_string(), set::new(vec![Primitive::Int(2), Primitive::Int(1)])),
+                ("inner".to_string(), dict::new([("b".to_string(), Value::from(-1i64)), ("a".to_string(), dict::new(Default::default()))].into())),
+            ]
+            .into(),
+        );
+        assert_eq!(
+            nested.to_json_string().unwrap(),
+            r#"{"count":3,"ids":[1,2],"inner":{"a":{},"b":-1},"name":"say \"hi\"\n\\ \u0001","ratio":2.0,"tags":[true,null,[]],"tiny":1e-9}"#
+        );
+        assert_eq!(Value::from("é").to_json_string().unwrap(), "\"é\"");
+    }
+
+    #[test]
+    fn json_string_errors() {
+        let f = function::new(Rc::new(|_: &[Value]| Ok(Value::from(1i64))));
+        assert!(matches!(f.to_json_string(), Err(Error::TypeMismatch(message)) if message == "cannot convert function to JSON"));
+        assert!(list::new(vec![Value::from(1i64), f]).to_json_string().is_err());
+        assert!(Value::from(f64::INFINITY).to_json_string().is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::types::expression::{BinaryOp, Expr, UnaryOp};
+use crate::types::primitive::Primitive;
+
+/// Read-only traversal of an `Expr`. Every `visit_*` method descends into the node's children by default,
+/// so an implementation only overrides the node kinds it cares about.
+pub trait Visitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+    fn visit_literal(&mut self, _value: &Primitive) {}
+    fn visit_var(&mut self, _name: &str) {}
+    fn visit_list(&mut self, items: &[Expr]) {
+        for item in items {
+            self.visit_expr(item);
+        }
+    }
+    fn visit_dict(&mut self, pairs: &[(Expr, Expr)]) {
+        for (key, value) in pairs {
+            self.visit_expr(key);
+            self.visit_expr(value);
+        }
+    }
+    fn visit_set(&mut self, items: &[Expr]) {
+        for item in items {
+            self.visit_expr(item);
+        }
+    }
+    fn visit_member(&mut self, object: &Expr, _field: &str) {
+        self.visit_expr(object);
+    }
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        self.visit_expr(object);
+        self.visit_expr(index);
+    }
+    fn visit_call(&mut self, callee: &Expr, args: &[Expr]) {
+        self.visit_expr(callee);
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+    fn visit_unary(&mut self, _op: UnaryOp, expr: &Expr) {
+        self.visit_expr(expr);
+    }
+    fn visit_binary(&mut self, _op: BinaryOp, left: &Expr, right: &Expr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+    fn visit_compare(&mut self, first: &Expr, rest: &[(BinaryOp, Expr)]) {
+        self.visit_expr(first);
+        for (_, operand) in rest {
+            self.visit_expr(operand);
+        }
+    }
+    fn visit_lambda(&mut self, _params: &[String], body: &Expr) {
+        self.visit_expr(body);
+    }
+    fn visit_let(&mut self, _name: &str, value: &Expr, body: &Expr) {
+        self.visit_expr(value);
+        self.visit_expr(body);
+    }
+    fn visit_assign(&mut self, object: &Expr, _field: &str, value: &Expr) {
+        self.visit_expr(object);
+        self.visit_expr(value);
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Literal(value) => visitor.visit_literal(value),
+        Expr::Var(name) => visitor.visit_var(name),
+        Expr::ListLiteral(items) => visitor.visit_list(items),
+        Expr::DictLiteral(pairs) => visitor.visit_dict(pairs),
+        Expr::SetLiteral(items) => visitor.visit_set(items),
+        Expr::Member { object, field } => visitor.visit_member(object, field),
+        Expr::Index { object, index } => visitor.visit_index(object, index),
+        Expr::Call { callee, args } => visitor.visit_call(callee, args),
+        Expr::Unary { op, expr } => visitor.visit_unary(*op, expr),
+        Expr::Binary { op, left, right } => visitor.visit_binary(*op, left, right),
+        Expr::Compare { first, rest } => visitor.visit_compare(first, rest),
+        Expr::Lambda { params, body } => visitor.visit_lambda(params, body),
+        Expr::Let { name, value, body } => visitor.visit_let(name, value, body),
+        Expr::Assign { object, field, value } => visitor.visit_assign(object, field, value),
+    }
+}
+
+/// In-place rewriting of an `Expr`. The default `visit_expr_mut` rewrites all children; override it to replace
+/// nodes, and call `walk_expr_mut` to keep descending.
+pub trait VisitorMut {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Literal(_) | Expr::Var(_) => {}
+        Expr::ListLiteral(items) | Expr::SetLiteral(items) => {
+            for item in items {
+                visitor.visit_expr_mut(item);
+            }
+        }
+        Expr::DictLiteral(pairs) => {
+            for (key, value) in pairs {
+                visitor.visit_expr_mut(key);
+                visitor.visit_expr_mut(value);
+            }
+        }
+        Expr::Member { object, .. } => visitor.visit_expr_mut(object),
+        Expr::Index { object, index } => {
+            visitor.visit_expr_mut(object);
+            visitor.visit_expr_mut(index);
+        }
+        Expr::Call { callee, args } => {
+            visitor.visit_expr_mut(callee);
+            for arg in args {
+                visitor.visit_expr_mut(arg);
+            }
+        }
+        Expr::Compare { first, rest } => {
+            visitor.visit_expr_mut(first);
+            for (_, operand) in rest {
+                visitor.visit_expr_mut(operand);
+            }
+        }
+        Expr::Unary { expr, .. } | Expr::Lambda { body: expr, .. } => visitor.visit_expr_mut(expr),
+        Expr::Binary { left, right, .. } | Expr::Let { value: left, body: right, .. } | Expr::Assign { object: left, value: right, .. } => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_expression;
+
+    #[test]
+    fn collect_called_names() {
+        struct Calls(Vec<String>);
+        impl Visitor for Calls {
+            fn visit_call(&mut self, callee: &Expr, args: &[Expr]) {
+                if let Expr::Var(name) = callee {
+                    self.0.push(name.clone());
+                }
+                walk_expr(self, callee);
+                for arg in args {
+                    self.visit_expr(arg);
+                }
+            }
+        }
+
+        let mut calls = Calls(Vec::new());
+        calls.visit_expr(&parse_expression("f(1, g(x)) + [h()][0] + {'k': i(2)}['k'] + obj.m()").unwrap());
+        assert_eq!(calls.0, vec!["f", "g", "h", "i"]);
+    }
+
+    #[test]
+    fn count_node_kinds() {
+        #[derive(Default)]
+        struct Kinds(std::collections::BTreeMap<&'static str, usize>);
+        impl Visitor for Kinds {
+            fn visit_expr(&mut self, expr: &Expr) {
+                let kind = match expr {
+                    Expr::Literal(_) => "literal",
+                    Expr::Var(_) => "var",
+                    Expr::ListLiteral(_) | Expr::DictLiteral(_) | Expr::SetLiteral(_) => "collection",
+                    Expr::Member { .. } => "member",
+                    Expr::Index { .. } => "index",
+                    Expr::Call { .. } => "call",
+                    Expr::Unary { .. } | Expr::Binary { .. } | Expr::Compare { .. } => "operator",
+                    Expr::Lambda { .. } => "lambda",
+                    Expr::Let { .. } => "let",
+                    Expr::Assign { .. } => "assign",
+                };
+                *self.0.entry(kind).or_default() += 1;
+                walk_expr(self, expr);
+            }
+        }
+
+        let mut kinds = Kinds::default();
+        kinds.visit_expr(&parse_expression("let n = -x.y in [n, 2][0] + f({'k': |a| a * n})").unwrap());
+        let counts: Vec<_> = kinds.0.into_iter().collect();
+        assert_eq!(
+            counts,
+            vec![("call", 1), ("collection", 2), ("index", 1), ("lambda", 1), ("let", 1), ("literal", 3), ("member", 1), ("operator", 3), ("var", 5)]
+        );
+    }
+
+    #[test]
+    fn rename_variables() {
+        struct Rename;
+        impl VisitorMut for Rename {
+            fn visit_expr_mut(&mut self, expr: &mut Expr) {
+                if let Expr::Var(name) = expr {
+                    *name = format!("ctx_{}", name);
+                }
+                walk_expr_mut(self, expr);
+            }
+        }
+
+        let mut expr = parse_expression("a + b.c * f(d)[e]").unwrap();
+        Rename.visit_expr_mut(&mut expr);
+        assert_eq!(expr, parse_expression("ctx_a + ctx_b.c * ctx_f(ctx_d)[ctx_e]").unwrap());
+    }
+}
@@ -0,0 +1,324 @@
+use crate::evaluator::is_implicit_function;
+use crate::types::dict::DictObject;
+use crate::types::error::Error;
+use crate::types::expression::{BinaryOp, Expr};
+use crate::types::list::ListObject;
+use crate::types::members::{Member, Members, member_names};
+use crate::types::primitive::Primitive;
+use crate::types::set::SetObject;
+use crate::types::visitor::{Visitor, walk_expr};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Reports errors that are certain to happen when `expr` is evaluated, without evaluating it and without a
+/// resolver: calling or indexing literals that do not support it, unknown members on literals and wrong argument
+/// counts for built-in methods called on literals. Anything that depends on a variable is assumed to be fine, and
+/// so is anything that might not be evaluated at all: the arguments of `try`, the right operand of `&&` and `||`,
+/// the operands of a comparison chain after the first comparison, and the bodies of lambdas and `@` functions. An
+/// empty result does not guarantee that evaluation succeeds.
+///
+/// Each error is an `Error::InvalidExpression` naming the offending sub-expression in source form, with the
+/// reason evaluation would give. An `Expr` does not remember where it was parsed from; `CompiledExpr::validate`
+/// also gives each error the span of the sub-expression (`Error::span`).
+pub fn validate(expr: &Expr) -> Vec<Error> {
+    validate_spanned(expr, None)
+}
+
+// `validate`, locating errors with the spans of `expr`'s nodes in visiting order, see `parser::parse_spanned`
+pub(crate) fn validate_spanned(expr: &Expr, spans: Option<&[Range<usize>]>) -> Vec<Error> {
+    let mut validator = Validator {
+        errors: Vec::new(),
+        spans,
+        next: 0,
+        node: 0,
+        unsure: 0,
+    };
+    validator.visit_expr(expr);
+    validator.errors
+}
+
+struct Validator<'a> {
+    errors: Vec<Error>,
+    spans: Option<&'a [Range<usize>]>,
+    // the visiting order index of the next node, and of the node being checked
+    next: usize,
+    node: usize,
+    // how many of the enclosing nodes might not be evaluated, in which case nothing is reported
+    unsure: usize,
+}
+
+impl Validator<'_> {
+    // Reports `expr`, the node being checked. Checks happen before a node's children are visited, so `node` is
+    // still its index.
+    fn report(&mut self, expr: Expr, reason: Error) {
+        if self.unsure > 0 {
+            return;
+        }
+        let error = Error::InvalidExpression {
+            expr: expr.to_source(),
+            reason: Box::new(reason),
+        };
+        match self.spans.and_then(|spans| spans.get(self.node)) {
+            Some(span) => self.errors.push(error.at(span.clone())),
+            None => self.errors.push(error),
+        }
+    }
+
+    fn visit_unsure(&mut self, expr: &Expr) {
+        self.unsure += 1;
+        self.visit_expr(expr);
+        self.unsure -= 1;
+    }
+}
+
+impl Visitor for Validator<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.node = self.next;
+        self.next += 1;
+        walk_expr(self, expr);
+    }
+
+    fn visit_member(&mut self, object: &Expr, field: &str) {
+        if let Some(type_name) = literal_type(object)
+            && !member_names(type_name).contains(&field)
+        {
+            let member = Expr::Member {
+                object: Box::new(object.clone()),
+                field: field.to_string(),
+            };
+            let reason = Error::UnknownMember {
+                type_name: type_name.into(),
+                member: field.to_string(),
+            };
+            self.report(member, reason);
+        }
+        self.visit_expr(object);
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        // strings and lists take int indices, dicts take string keys, sets neither, and nothing else is indexable.
+        // Like evaluation, blame the object if it is not a string or a collection, and the index otherwise.
+        let culprit = match (literal_type(object), index) {
+            (Some("string" | "list"), Expr::Literal(Primitive::Int(_))) | (Some("dict"), Expr::Literal(Primitive::Str(_))) => None,
+            (Some("string" | "list" | "dict" | "set"), Expr::Literal(index)) => Some(index.as_str_lossy()),
+            // the index is not known, or not as it would be shown
+            (Some("string" | "list" | "dict" | "set"), _) | (None, _) => None,
+            (Some(object_type), _) => Some(object_type.to_string()),
+        };
+        if let Some(culprit) = culprit {
+            let indexed = Expr::Index {
+                object: Box::new(object.clone()),
+                index: Box::new(index.clone()),
+            };
+            self.report(indexed, Error::NotIndexable(culprit));
+        }
+        self.visit_expr(object);
+        self.visit_expr(index);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, args: &[Expr]) {
+        let call = || Expr::Call {
+            callee: Box::new(callee.clone()),
+            args: args.to_vec(),
+        };
+        match callee {
+            Expr::Var(name) if name == "try" && args.len() != 2 => self.report(call(), Error::EvaluationFailed("try expects 2 args".into())),
+            Expr::Member { object, field } => {
+                if let Some(type_name) = literal_type(object) {
+                    match builtin_member(type_name, field) {
+                        Some(Member::Property(_)) => self.report(call(), Error::NotCallable),
+                        Some(Member::Method(_)) => match method_arity(type_name, field) {
+                            Some((min, max)) if args.len() < min || args.len() > max => {
+                                let expected = if min == max { format!("{}", min) } else { format!("{} to {}", min, max) };
+                                self.report(call(), Error::EvaluationFailed(format!("{} expects {} args, got {}", field, expected, args.len())));
+                            }
+                            _ => {}
+                        },
+                        // unknown members are reported by visit_member
+                        None => {}
+                    }
+                }
+            }
+            _ if literal_type(callee).is_some() => self.report(call(), Error::NotCallable),
+            _ => {}
+        }
+        let is_try = matches!(callee, Expr::Var(name) if name == "try");
+        let method_call = matches!(callee, Expr::Member { .. });
+        self.visit_expr(callee);
+        for arg in args {
+            if is_try || (method_call && is_implicit_function(arg)) {
+                self.visit_unsure(arg);
+            } else {
+                self.visit_expr(arg);
+            }
+        }
+    }
+
+    fn visit_binary(&mut self, op: BinaryOp, left: &Expr, right: &Expr) {
+        self.visit_expr(left);
+        if matches!(op, BinaryOp::And | BinaryOp::Or) {
+            self.visit_unsure(right);
+        } else {
+            self.visit_expr(right);
+        }
+    }
+
+    fn visit_compare(&mut self, first: &Expr, rest: &[(BinaryOp, Expr)]) {
+        self.visit_expr(first);
+        for (i, (_, operand)) in rest.iter().enumerate() {
+            if i == 0 {
+                self.visit_expr(operand);
+            } else {
+                self.visit_unsure(operand);
+            }
+        }
+    }
+
+    fn visit_lambda(&mut self, _params: &[String], body: &Expr) {
+        self.visit_unsure(body);
+    }
+}
+
+// The type an expression is guaranteed to evaluate to, as reported by `Value::type_name`, if it is a literal.
+fn literal_type(expr: &Expr) -> Option<&'static str> {
+    match expr {
+        Expr::Literal(Primitive::Str(_)) => Some("string"),
+        Expr::Literal(Primitive::Bool(_)) => Some("bool"),
+        Expr::Literal(Primitive::Null) => Some("null"),
+        Expr::Literal(_) => Some("number"),
+        Expr::ListLiteral(_) => Some(ListObject::TYPE_NAME),
+        Expr::DictLiteral(_) => Some(DictObject::TYPE_NAME),
+        Expr::SetLiteral(_) => Some(SetObject::TYPE_NAME),
+        _ => None,
+    }
+}
+
+// Whether a member is a property or a method does not depend on the receiver's contents, so an empty value of the
+// right type answers it.
+fn builtin_member(type_name: &str, name: &str) -> Option<Member> {
+    match type_name {
+        "string" => "".member(name),
+        "number" => 0i64.member(name),
+        "list" => ListObject::new(Vec::new()).member(name),
+        "dict" => DictObject::new(BTreeMap::new()).member(name),
+        "set" => SetObject::new(Vec::new()).member(name),
+        _ => None,
+    }
+}
+
+// Accepted argument counts (inclusive) of the built-in methods.
+fn method_arity(type_name: &str, name: &str) -> Option<(usize, usize)> {
+    let arity = match (type_name, name) {
+        ("string", "toUpper" | "toLower" | "trim" | "lines" | "capitalize" | "title" | "toInt" | "toFloat") => (0, 0),
+        ("string", "contains" | "split" | "startsWith" | "endsWith" | "indexOf" | "equalsIgnoreCase") => (1, 1),
+        #[cfg(feature = "regex")]
+        ("string", "matches" | "find") => (1, 1),
+        #[cfg(feature = "regex")]
+        ("string", "replaceAll") => (2, 2),
+        ("string", "substring" | "slice") => (1, 2),
+        ("number", "abs") => (0, 0),
+        ("list", "contains" | "join" | "map" | "flatMap" | "chunk" | "groupBy" | "filter" | "indexOf") => (1, 1),
+        ("list", "sum" | "min" | "max" | "avg" | "reverse" | "first" | "last" | "distinct") => (0, 0),
+        ("list", "get" | "reduce") => (2, 2),
+        ("list", "slice") => (1, 2),
+        ("dict", "keys" | "values" | "entries") => (0, 0),
+        ("dict", "contains" | "filter" | "merge" | "remove" | "mapValues") => (1, 1),
+        ("dict", "get" | "set") => (2, 2),
+        ("set", "toList") => (0, 0),
+        ("set", "contains" | "union" | "intersection" | "difference") => (1, 1),
+        _ => return None,
+    };
+    Some(arity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::{CompiledExpr, Evaluator};
+    use crate::parser::parse_expression;
+    use crate::resolvers::NoVars;
+
+    fn errors(src: &str) -> Vec<String> {
+        validate(&parse_expression(src).unwrap()).iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn flags_guaranteed_failures() {
+        assert_eq!(errors("1(2)"), vec!["1(2): variable is not callable"]);
+        assert_eq!(errors("'abc'.length()"), vec!["\"abc\".length(): variable is not callable"]);
+        assert_eq!(errors("true[0]"), vec!["true[0]: not indexable: bool"]);
+        assert_eq!(errors("'abc'['x']"), vec!["\"abc\"[\"x\"]: not indexable: x"]);
+        assert_eq!(errors("{'a': 1}[0]"), vec!["{\"a\": 1}[0]: not indexable: 0"]);
+        assert_eq!(errors("[1][1.5]"), vec!["[1][1.5]: not indexable: 1.5"]);
+        assert_eq!(errors("false[x]"), vec!["false[x]: not indexable: bool"]);
+        assert_eq!(errors("[1, 2].size"), vec!["[1, 2].size: unknown member 'size' for type list"]);
+        assert_eq!(errors("'abc'.toUpper(1)"), vec!["\"abc\".toUpper(1): evaluation failed: toUpper expects 0 args, got 1"]);
+        assert_eq!(errors("'abc'.substring()"), vec!["\"abc\".substring(): evaluation failed: substring expects 1 to 2 args, got 0"]);
+        assert_eq!(errors("try(1)"), vec!["try(1): evaluation failed: try expects 2 args"]);
+        // nested problems are all reported
+        assert_eq!(errors("f(true.x, [1][0.5])").len(), 2);
+    }
+
+    #[test]
+    fn reports_what_evaluation_reports() {
+        for src in ["1(2)", "'abc'.length()", "true[0]", "null['a']", "'abc'['x']", "{'a': 1}[0]", "[1]['a']", "[1][true]", "[1, 2].size"] {
+            let expr = parse_expression(src).unwrap();
+            let evaluated = Evaluator::new(&NoVars).evaluate(&expr).unwrap_err();
+            match &validate(&expr)[..] {
+                [Error::InvalidExpression { reason, .. }] => assert_eq!(reason.to_string(), evaluated.to_string(), "{}", src),
+                other => panic!("'{}' should have one error, got {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn locates_errors() {
+        let spans = |src: &str| CompiledExpr::compile(src).unwrap().validate().iter().map(|e| src[e.span().unwrap()].to_string()).collect::<Vec<_>>();
+        assert_eq!(spans("x + true[0] * 2"), vec!["true[0]"]);
+        assert_eq!(spans("f([1].size, 1(2))"), vec!["[1].size", "1(2)"]);
+        assert_eq!(spans("[1, 2].map(|n| n)[0]['a']"), Vec::<String>::new());
+        assert_eq!(spans("let a = 'abc'.toUpper(1) in a"), vec!["'abc'.toUpper(1)"]);
+        assert_eq!(validate(&parse_expression("true[0]").unwrap())[0].span(), None);
+    }
+
+    #[test]
+    fn accepts_anything_that_might_succeed() {
+        for src in [
+            "f(1)",
+            "x.anything(1, 2, 3)",
+            "x[0]",
+            "[1, 2][i]",
+            "{'a': 1}[key]",
+            "'abc'[0]",
+            "'abc'.substring(1, 2)",
+            "[1].get(0, 2)",
+            "{}.keys()",
+            "(1 + 2).abs()",
+            "try(a, b)",
+            // only evaluated if something else fails, or succeeds
+            "try(true[0], 0)",
+            "try(x, 1(2))",
+            "false && 1(2)",
+            "x || 'a'.nope",
+            "1 > 2 > 'a'[true]",
+            // functions that are never called
+            "|x| true[0]",
+            "[].map(|n| 1(2))",
+            "[].map(@ + true[0])",
+            "let f = |x| x.y + 1(2) in 1",
+        ] {
+            assert!(errors(src).is_empty(), "'{}' should be valid, got {:?}", src, errors(src));
+        }
+    }
+
+    #[test]
+    fn every_builtin_method_has_an_arity() {
+        for type_name in ["string", "number", "list", "dict", "set"] {
+            for name in member_names(type_name) {
+                if let Some(Member::Method(_)) = builtin_member(type_name, name) {
+                    assert!(method_arity(type_name, name).is_some(), "no arity for {}.{}", type_name, name);
+                }
+            }
+        }
+    }
+}